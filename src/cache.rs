@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Cursor, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{CacheMode, KvContext, kv_project_dirs};
+
+/// Subdirectory of the cache directory holding final rasters.
+const RENDER_CACHE_DIR: &str = "renders";
+/// Subdirectory of the cache directory listing the local files loaded by rendered HTML.
+const MANIFEST_CACHE_DIR: &str = "manifests";
+
+#[cfg(test)]
+mod tests_cache;
+
+/// A file in the cache directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Last time the entry was written or used.
+    pub last_used: SystemTime,
+}
+
+/// Returns the cache directory, or `None` if caching is disabled.
+pub fn cache_dir(mode: &CacheMode) -> Option<PathBuf> {
+    match mode {
+        CacheMode::Disabled => None,
+        CacheMode::Default => Some(kv_project_dirs().cache_dir),
+        CacheMode::Custom(path) => Some(path.clone()),
+    }
+}
+
+/// Cache files are named by the SHA-256 of their content, so that unrelated
+/// files in a custom cache directory are never touched. Lock files are not entries.
+fn is_cache_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.get(..64))
+        .is_some_and(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))
+        && path.extension().is_none_or(|ext| ext != "lock")
+}
+
+/// Source copies of office documents written next to the PDF by older versions.
+fn is_source_copy(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext != "pdf" && ext != "png" && ext != "json")
+}
+
+/// Removes a cache file, one already removed by another process is not an error.
+fn remove_cache_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn collect_entries(dir: &Path, entries: &mut Vec<CacheEntry>) -> Result<()> {
+    // other processes may remove entries and directories while they are listed
+    let dir_entries = match std::fs::read_dir(dir) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        dir_entries => dir_entries?,
+    };
+    for dir_entry in dir_entries {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let metadata = match dir_entry.metadata() {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            metadata => metadata?,
+        };
+
+        if metadata.is_dir() {
+            collect_entries(&path, entries)?;
+        } else if metadata.is_file() && is_cache_file(&path) {
+            entries.push(CacheEntry {
+                path,
+                size: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Lists all cache entries, least recently used first.
+pub fn cache_entries(dir: &Path) -> Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+    if dir.exists() {
+        collect_entries(dir, &mut entries)
+            .with_context(|| format!("Failed to read cache directory: {}", dir.display()))?;
+    }
+    entries.sort_by(|a, b| a.last_used.cmp(&b.last_used).then(a.path.cmp(&b.path)));
+    Ok(entries)
+}
+
+/// Returns the total size of all cache entries in bytes.
+pub fn cache_size(dir: &Path) -> Result<u64> {
+    Ok(cache_entries(dir)?.iter().map(|entry| entry.size).sum())
+}
+
+/// Removes all cache entries and returns them.
+pub fn clear_cache(dir: &Path) -> Result<Vec<CacheEntry>> {
+    let entries = cache_entries(dir)?;
+    for entry in &entries {
+        remove_cache_file(&entry.path)?;
+    }
+    Ok(entries)
+}
+
+/// Removes leftover source copies, entries unused for longer than `older_than`, and the
+/// least recently used entries until the cache fits into `max_size`. Returns the removed entries.
+pub fn prune_cache(
+    dir: &Path,
+    older_than: Option<Duration>,
+    max_size: Option<u64>,
+) -> Result<Vec<CacheEntry>> {
+    let now = SystemTime::now();
+    let (mut removed, kept): (Vec<_>, Vec<_>) =
+        cache_entries(dir)?.into_iter().partition(|entry| {
+            is_source_copy(&entry.path)
+                || older_than.is_some_and(|older_than| {
+                    now.duration_since(entry.last_used).unwrap_or_default() > older_than
+                })
+        });
+
+    if let Some(max_size) = max_size {
+        let mut total_size: u64 = kept.iter().map(|entry| entry.size).sum();
+        // entries are sorted from least to most recently used
+        for entry in kept {
+            if total_size <= max_size {
+                break;
+            }
+            total_size -= entry.size;
+            removed.push(entry);
+        }
+    }
+
+    for entry in &removed {
+        remove_cache_file(&entry.path)?;
+    }
+    Ok(removed)
+}
+
+/// Marks a cache entry as used, so it is evicted last.
+pub fn touch_cache_entry(path: &Path) {
+    if let Ok(file) = File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+/// Writes a cache entry atomically, readers never see a partially written file.
+pub fn write_cache_file(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .context("Cache file has no parent directory")?;
+    std::fs::create_dir_all(dir).context("Failed to create cache directory")?;
+
+    // the temporary file must be on the same file system for the rename to be atomic
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(data)?;
+    file.persist(path)
+        .with_context(|| format!("Failed to write cache file: {}", path.display()))?;
+    Ok(())
+}
+
+/// An exclusive lock on a cache entry, released and removed on drop.
+#[derive(Debug)]
+pub struct CacheLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // waiting processes hold the removed file, they notice it after locking and retry.
+        // Windows cannot create a file while its removal is pending, so it is kept there.
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Checks that the locked file is still the lock file at `path`, not one removed by the
+/// process that held the lock before.
+fn is_current_lock(file: &File, path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (file.metadata(), std::fs::metadata(path)) {
+            (Ok(locked), Ok(current)) => {
+                locked.dev() == current.dev() && locked.ino() == current.ino()
+            }
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (file, path);
+        true
+    }
+}
+
+/// Locks the cache entry `key` in `dir`, waiting while another process holds the lock.
+pub fn lock_cache_entry(dir: &Path, key: &str) -> Result<CacheLock> {
+    std::fs::create_dir_all(dir).context("Failed to create cache directory")?;
+    let path = dir.join(format!("{}.lock", key));
+    loop {
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create lock file: {}", path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock: {}", path.display()))?;
+        if is_current_lock(&file, &path) {
+            return Ok(CacheLock { file, path });
+        }
+    }
+}
+
+/// Removes least recently used entries if the cache exceeds its size limit.
+pub fn evict_cache(ctx: &KvContext) -> Result<()> {
+    if let (Some(dir), Some(max_size)) = (cache_dir(&ctx.cache_mode), ctx.cache_max_size) {
+        prune_cache(&dir, None, Some(max_size)).context("Failed to evict cache entries")?;
+    }
+    Ok(())
+}
+
+/// Hashes the content of an input together with all parameters affecting its render.
+pub fn render_cache_key(ctx: &KvContext, kind: &str, content: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    for part in content {
+        // length prefix, so parts cannot shift into each other
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let params = format!(
+        "{:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+        ctx.resize_mode,
+        ctx.term_size,
+        ctx.page_indices,
+        ctx.background_color,
+        ctx.html,
+        ctx.markdown_theme,
+        ctx.image
+    );
+    hasher.update(params.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Returns the cached render of an input, or renders and caches it.
+pub fn cached_render(
+    ctx: &KvContext,
+    kind: &str,
+    content: &[&[u8]],
+    render: impl FnOnce() -> Result<DynamicImage>,
+) -> Result<DynamicImage> {
+    let Some(dir) = cache_dir(&ctx.cache_mode) else {
+        return render();
+    };
+    let dir = dir.join(RENDER_CACHE_DIR);
+    let path = dir.join(format!("{}.png", render_cache_key(ctx, kind, content)));
+
+    if path.exists() {
+        touch_cache_entry(&path);
+        if let Ok(img) = image::open(&path) {
+            return Ok(img);
+        }
+    }
+
+    let img = render()?;
+
+    // a failing cache must not fail the render
+    let mut png = Vec::new();
+    let stored = img
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| write_cache_file(&path, &png));
+    if let Err(e) = stored.and_then(|_| evict_cache(ctx)) {
+        eprintln!("Warning: Failed to cache render: {:#}", e);
+    }
+
+    Ok(img)
+}
+
+/// Returns the manifest of the local files the input cached under `key` loaded when it was
+/// last rendered, or `None` if caching is disabled.
+pub fn loaded_files_manifest(ctx: &KvContext, key: &str) -> Option<PathBuf> {
+    let dir = cache_dir(&ctx.cache_mode)?;
+    Some(dir.join(MANIFEST_CACHE_DIR).join(format!("{}.json", key)))
+}
+
+/// Returns the current content of the files listed in a manifest, to be added to the cache
+/// key. `None` if there is no manifest yet.
+pub fn read_loaded_files(manifest: &Path) -> Option<Vec<Vec<u8>>> {
+    let files: Vec<PathBuf> = serde_json::from_slice(&std::fs::read(manifest).ok()?).ok()?;
+    touch_cache_entry(manifest);
+    let content = files
+        .iter()
+        .map(|path| {
+            // a missing file is keyed by its path only, it may appear later
+            let mut part = path.to_string_lossy().as_bytes().to_vec();
+            if let Ok(data) = std::fs::read(path) {
+                part.push(0);
+                part.extend(data);
+            }
+            part
+        })
+        .collect();
+    Some(content)
+}
+
+/// Writes the manifest of the local files an input loaded while rendering.
+pub fn write_loaded_files(manifest: &Path, files: &BTreeSet<PathBuf>) -> Result<()> {
+    write_cache_file(manifest, &serde_json::to_vec(files)?)
+}
@@ -0,0 +1,248 @@
+use super::*;
+use image::{GenericImageView, Rgba};
+use rstest::rstest;
+
+const HASH_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+const HASH_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+const HASH_C: &str = "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc";
+
+/// Creates a cache file with the given size, last used `age` ago.
+fn create_entry(dir: &Path, name: &str, size: usize, age: Duration) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, vec![0u8; size]).unwrap();
+    let file = File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+    path
+}
+
+fn create_cache() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let hour = Duration::from_secs(60 * 60);
+    create_entry(dir.path(), &format!("{}.pdf", HASH_A), 100, hour * 48);
+    create_entry(dir.path(), &format!("{}.pdf", HASH_B), 200, hour * 2);
+    create_entry(dir.path(), &format!("{}.docx", HASH_C), 50, hour);
+    // unrelated files are never part of the cache
+    create_entry(dir.path(), "notes.txt", 1000, hour * 100);
+    dir
+}
+
+fn file_names(entries: &[CacheEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn test_cache_entries() {
+    let dir = create_cache();
+    let entries = cache_entries(dir.path()).unwrap();
+    assert_eq!(
+        file_names(&entries),
+        vec![
+            format!("{}.pdf", HASH_A),
+            format!("{}.pdf", HASH_B),
+            format!("{}.docx", HASH_C)
+        ]
+    );
+    assert_eq!(cache_size(dir.path()).unwrap(), 350);
+}
+
+#[test]
+fn test_cache_entries_missing_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = cache_entries(&dir.path().join("missing")).unwrap();
+    assert!(entries.is_empty());
+}
+
+#[test]
+fn test_clear_cache() {
+    let dir = create_cache();
+    let removed = clear_cache(dir.path()).unwrap();
+    assert_eq!(removed.len(), 3);
+    assert!(cache_entries(dir.path()).unwrap().is_empty());
+    assert!(dir.path().join("notes.txt").exists());
+}
+
+#[rstest]
+#[case(None, None, vec![HASH_C])] // source copies only
+#[case(Some(Duration::from_secs(24 * 60 * 60)), None, vec![HASH_A, HASH_C])]
+#[case(None, Some(250), vec![HASH_C, HASH_A])]
+#[case(None, Some(100), vec![HASH_C, HASH_A, HASH_B])]
+#[case(None, Some(1000), vec![HASH_C])]
+fn test_prune_cache(
+    #[case] older_than: Option<Duration>,
+    #[case] max_size: Option<u64>,
+    #[case] expected_removed: Vec<&str>,
+) {
+    let dir = create_cache();
+    let removed = prune_cache(dir.path(), older_than, max_size).unwrap();
+    let removed_hashes: Vec<_> = file_names(&removed)
+        .into_iter()
+        .map(|name| name[..64].to_string())
+        .collect();
+    assert_eq!(removed_hashes, expected_removed);
+    for entry in removed {
+        assert!(!entry.path.exists());
+    }
+}
+
+#[test]
+fn test_touch_cache_entry() {
+    let dir = create_cache();
+    let path = dir.path().join(format!("{}.pdf", HASH_A));
+    touch_cache_entry(&path);
+    let entries = cache_entries(dir.path()).unwrap();
+    assert_eq!(entries.last().unwrap().path, path);
+}
+
+fn test_ctx(cache_mode: CacheMode) -> KvContext {
+    KvContext {
+        term_size: (100, 50),
+        page_indices: Some(vec![0]),
+        cache_mode,
+        ..KvContext::default()
+    }
+}
+
+#[test]
+fn test_render_cache_key() {
+    let ctx = test_ctx(CacheMode::Disabled);
+    let key = render_cache_key(&ctx, "pdf", &[b"data"]);
+    assert_eq!(key.len(), 64);
+    assert_eq!(key, render_cache_key(&ctx, "pdf", &[b"data"]));
+
+    // content, kind and render parameters are part of the key
+    assert_ne!(key, render_cache_key(&ctx, "pdf", &[b"other"]));
+    assert_ne!(key, render_cache_key(&ctx, "svg", &[b"data"]));
+    assert_ne!(key, render_cache_key(&ctx, "pdf", &[b"da", b"ta"]));
+    let mut other_ctx = test_ctx(CacheMode::Disabled);
+    other_ctx.page_indices = None;
+    assert_ne!(key, render_cache_key(&other_ctx, "pdf", &[b"data"]));
+    other_ctx = test_ctx(CacheMode::Disabled);
+    other_ctx.background_color = Some(Rgba([0, 0, 0, 255]));
+    assert_ne!(key, render_cache_key(&other_ctx, "pdf", &[b"data"]));
+}
+
+#[test]
+fn test_cached_render() {
+    let dir = tempfile::tempdir().unwrap();
+    let ctx = test_ctx(CacheMode::Custom(dir.path().to_path_buf()));
+    let render = || Ok(DynamicImage::new_rgba8(2, 1));
+
+    let img = cached_render(&ctx, "test", &[b"data"], render).unwrap();
+    assert_eq!(img.dimensions(), (2, 1));
+    assert_eq!(cache_entries(dir.path()).unwrap().len(), 1);
+
+    // a cache hit does not render again
+    let cached = cached_render(&ctx, "test", &[b"data"], || anyhow::bail!("rendered twice"));
+    assert_eq!(cached.unwrap().dimensions(), (2, 1));
+}
+
+#[test]
+fn test_cached_render_disabled() {
+    let ctx = test_ctx(CacheMode::Disabled);
+    let result = cached_render(&ctx, "test", &[b"data"], || anyhow::bail!("render failed"));
+    assert_eq!(result.unwrap_err().to_string(), "render failed");
+}
+
+#[test]
+fn test_write_cache_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("renders").join(format!("{}.png", HASH_A));
+    write_cache_file(&path, b"first").unwrap();
+    write_cache_file(&path, b"second").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    // no temporary files are left behind
+    assert_eq!(
+        std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+        1
+    );
+}
+
+#[test]
+fn test_lock_cache_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock_path = dir.path().join(format!("{}.lock", HASH_A));
+
+    let lock = lock_cache_entry(dir.path(), HASH_A).unwrap();
+    assert!(lock_path.exists());
+    // lock files are not cache entries
+    assert!(cache_entries(dir.path()).unwrap().is_empty());
+    let other = File::open(&lock_path).unwrap();
+    assert!(other.try_lock().is_err());
+
+    drop(lock);
+    #[cfg(unix)]
+    assert!(!lock_path.exists());
+    assert!(other.try_lock().is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_lock_cache_entry_removed_while_waiting() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock_path = dir.path().join(format!("{}.lock", HASH_A));
+
+    let lock = lock_cache_entry(dir.path(), HASH_A).unwrap();
+    let waiting = {
+        let dir = dir.path().to_path_buf();
+        std::thread::spawn(move || lock_cache_entry(&dir, HASH_A).unwrap())
+    };
+    std::thread::sleep(Duration::from_millis(100));
+    drop(lock);
+
+    // the waiting lock must hold the file others open, not the removed one
+    let waiting = waiting.join().unwrap();
+    let other = File::open(&lock_path).unwrap();
+    assert!(other.try_lock().is_err());
+    drop(waiting);
+    assert!(other.try_lock().is_ok());
+}
+
+#[test]
+fn test_remove_missing_cache_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = create_entry(dir.path(), HASH_A, 10, Duration::ZERO);
+    std::fs::remove_file(&path).unwrap();
+    assert!(remove_cache_file(&path).is_ok());
+    assert!(clear_cache(&dir.path().join("missing")).unwrap().is_empty());
+}
+
+#[test]
+fn test_loaded_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let ctx = test_ctx(CacheMode::Custom(dir.path().to_path_buf()));
+    let manifest = loaded_files_manifest(&ctx, HASH_A).unwrap();
+    assert_eq!(read_loaded_files(&manifest), None);
+
+    let style = dir.path().join("style.css");
+    std::fs::write(&style, "body {}").unwrap();
+    let missing = dir.path().join("missing.png");
+    write_loaded_files(&manifest, &BTreeSet::from([style.clone(), missing])).unwrap();
+    let content = read_loaded_files(&manifest).unwrap();
+    assert_eq!(content.len(), 2);
+
+    // changed and created files change the content, missing ones are keyed by their path
+    std::fs::write(&style, "body { color: red }").unwrap();
+    let changed = read_loaded_files(&manifest).unwrap();
+    assert_ne!(changed, content);
+    std::fs::write(dir.path().join("missing.png"), b"").unwrap();
+    assert_ne!(read_loaded_files(&manifest).unwrap(), changed);
+
+    // manifests are cache entries, but not source copies
+    assert_eq!(cache_entries(dir.path()).unwrap().len(), 1);
+    assert!(prune_cache(dir.path(), None, None).unwrap().is_empty());
+
+    assert_eq!(
+        loaded_files_manifest(&test_ctx(CacheMode::Disabled), HASH_A),
+        None
+    );
+}
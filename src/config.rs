@@ -1,7 +1,10 @@
-use crate::InputType;
+use crate::{InputType, PluginOptions, has_magic_at, mime_matches};
 use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::bytes::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[cfg(target_os = "macos")]
 use std::env;
@@ -9,21 +12,209 @@ use std::env;
 #[cfg(not(target_os = "macos"))]
 use directories::ProjectDirs;
 
+/// Number of bytes the header regex of a plugin is matched against.
+const HEADER_SIZE: usize = 4096;
+
+/// Magic bytes at an offset of the data.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Magic {
+    #[serde(default)]
+    pub offset: usize,
+    /// Hex string, spaces are ignored.
+    pub bytes: String,
+}
+
+impl Magic {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        hex::decode(self.bytes.replace(" ", ""))
+            .is_ok_and(|magic| has_magic_at(data, self.offset, &magic))
+    }
+}
+
+/// Compiles the header regex while loading, so invalid patterns are reported as config errors.
+fn deserialize_regex<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Regex>, D::Error> {
+    let pattern = <String as serde::Deserialize>::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Globs of a plugin, compiled into one set while loading.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "Vec<Glob>")]
+pub struct Globs {
+    globs: Vec<Glob>,
+    set: GlobSet,
+}
+
+impl TryFrom<Vec<Glob>> for Globs {
+    type Error = globset::Error;
+
+    fn try_from(globs: Vec<Glob>) -> std::result::Result<Self, Self::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in &globs {
+            builder.add(glob.clone());
+        }
+        let set = builder.build()?;
+        Ok(Self { globs, set })
+    }
+}
+
+impl Globs {
+    /// Returns the patterns as configured.
+    pub fn patterns(&self) -> Vec<&str> {
+        self.globs.iter().map(|glob| glob.glob()).collect()
+    }
+
+    /// Checks the globs against the whole path and its file name.
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.set.is_match(path) || path.file_name().is_some_and(|name| self.set.is_match(name))
+    }
+}
+
+/// When a plugin runs relative to the built-in handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginMode {
+    /// Run instead of the built-in handlers.
+    #[default]
+    Override,
+    /// Run only if the built-in handlers fail.
+    Fallback,
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 // rename magic-bytes to magic_bytes and output-placeholder to output_placeholder
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Plugin {
+    #[serde(default)]
     pub extensions: Vec<String>,
     pub magic_bytes: Option<Vec<String>>,
+    /// Magic bytes at an offset, e.g. behind a length field.
+    pub magic: Option<Vec<Magic>>,
+    /// MIME types sniffed from the content, e.g. `image/png` or `image/*`.
+    pub mime_types: Option<Vec<String>>,
+    /// Globs matched against the file name and path, e.g. `*.tar.gz` or `Dockerfile`.
+    pub globs: Option<Globs>,
+    /// Regular expression matched against the first bytes of the data.
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub header_regex: Option<Regex>,
     pub output: InputType,
     pub path: String,
     pub placeholder: Option<String>,
     pub output_placeholder: Option<String>,
+    /// Plugins with a higher priority are tried first, equal priorities by name.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub mode: PluginMode,
+    /// Seconds until the plugin and its child processes are killed, unlimited if unset or 0.
+    pub timeout: Option<u64>,
+    /// Additional environment variables (sorted, so the cache key is stable).
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Working directory of the plugin, otherwise the current directory.
+    pub cwd: Option<PathBuf>,
+    /// Close stdin of plugins reading their input from a file placeholder.
+    #[serde(default)]
+    pub stdin_closed: bool,
+    /// The output placeholder is a directory, its files are read in name order as pages.
+    #[serde(default)]
+    pub output_dir: bool,
+}
+
+impl Plugin {
+    /// Returns the configured timeout, `None` if plugins may run as long as they need.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Returns the program of the command.
+    pub fn program(&self) -> Option<String> {
+        shell_words::split(&self.path).ok()?.into_iter().next()
+    }
+
+    /// Checks the configuration for errors that make every run fail.
+    pub fn validate(&self) -> Result<()> {
+        let command_parts =
+            shell_words::split(&self.path).context("Invalid command string in plugin config")?;
+        if command_parts.is_empty() {
+            anyhow::bail!("Plugin command is empty");
+        }
+
+        if let (Some(i), Some(o)) = (&self.placeholder, &self.output_placeholder)
+            && i == o
+        {
+            anyhow::bail!("Input placeholder equals output placeholder");
+        }
+        if self.stdin_closed && self.placeholder.is_none() {
+            anyhow::bail!("Plugin with closed stdin requires an input placeholder");
+        }
+        if self.output_dir && self.output_placeholder.is_none() {
+            anyhow::bail!("Plugin with output directory requires an output placeholder");
+        }
+        Ok(())
+    }
+
+    /// Describes the conditions of the plugin, e.g. for listing plugins.
+    pub fn matchers(&self) -> Vec<String> {
+        let mut matchers = Vec::new();
+        if !self.extensions.is_empty() {
+            matchers.push(format!("extensions {}", self.extensions.join(", ")));
+        }
+        if let Some(magic_bytes) = &self.magic_bytes {
+            matchers.push(format!("magic-bytes {}", magic_bytes.join(", ")));
+        }
+        for magic in self.magic.iter().flatten() {
+            matchers.push(format!("magic {} at {}", magic.bytes, magic.offset));
+        }
+        if let Some(mime_types) = &self.mime_types {
+            matchers.push(format!("mime-types {}", mime_types.join(", ")));
+        }
+        if let Some(globs) = &self.globs {
+            matchers.push(format!("globs {}", globs.patterns().join(", ")));
+        }
+        if let Some(regex) = &self.header_regex {
+            matchers.push(format!("header-regex {}", regex.as_str()));
+        }
+        matchers
+    }
+
+    /// Checks if any of the conditions of the plugin matches the data or its file path.
+    ///
+    /// `mime` is the type sniffed from the data, so it is sniffed once for all plugins.
+    pub fn matches(&self, data: &[u8], mime: &str, extension: &str, path: Option<&Path>) -> bool {
+        has_extension_or_magic_bytes(
+            data,
+            extension,
+            self.magic_bytes.as_deref().unwrap_or_default(),
+            &self.extensions,
+        ) || self.magic.iter().flatten().any(|magic| magic.matches(data))
+            || self
+                .mime_types
+                .as_ref()
+                .is_some_and(|patterns| patterns.iter().any(|pattern| mime_matches(mime, pattern)))
+            || path.is_some_and(|path| {
+                self.globs
+                    .as_ref()
+                    .is_some_and(|globs| globs.is_match(path))
+            })
+            || self
+                .header_regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(&data[..data.len().min(HEADER_SIZE)]))
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
+// transparent instead of flatten keeps the line numbers in errors of the plugins
+#[serde(transparent)]
 pub struct PluginConfig {
-    #[serde(flatten)]
     pub plugins: HashMap<String, Plugin>,
 }
 
@@ -74,26 +265,232 @@ pub fn get_config_path() -> PathBuf {
     kv_project_dirs().config_dir.join("plugins.toml")
 }
 
-pub fn load_plugins() -> HashMap<String, Plugin> {
-    let config_path = get_config_path();
+/// Defaults for command line options, read from `config.toml` and `KV_*` environment variables.
+/// Values are validated when they are applied to the options.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Settings {
+    pub background: Option<bool>,
+    pub color: Option<String>,
+    pub mode: Option<String>,
+    pub resize: Option<String>,
+    pub cache_dir: Option<PathBuf>,
+    pub markdown_theme: Option<String>,
+    pub theme: Option<String>,
+    pub font: Option<String>,
+    pub pdfium: Option<PathBuf>,
+    pub browser: Option<PathBuf>,
+    /// Load `.kv/plugins.toml` of the current directory, only for trusted setups.
+    pub project_plugins: Option<bool>,
+}
+
+/// Parses the boolean value of the environment variable `name`, an invalid value is skipped
+/// with a warning.
+fn env_bool(name: &str, value: Option<String>) -> Option<bool> {
+    match value.as_deref() {
+        None => None,
+        Some("1" | "true" | "yes" | "on") => Some(true),
+        Some("0" | "false" | "no" | "off") => Some(false),
+        Some(value) => {
+            eprintln!("Warning: Ignoring invalid boolean in {}: {}", name, value);
+            None
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the `KV_*` variables (e.g. `KV_MODE`, `KV_CACHE_DIR`) with `var`, empty values are unset.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        Self {
+            background: env_bool("KV_BACKGROUND", var("KV_BACKGROUND")),
+            color: var("KV_COLOR"),
+            mode: var("KV_MODE"),
+            resize: var("KV_RESIZE"),
+            cache_dir: var("KV_CACHE_DIR").map(PathBuf::from),
+            markdown_theme: var("KV_MARKDOWN_THEME"),
+            theme: var("KV_THEME"),
+            font: var("KV_FONT"),
+            pdfium: var("KV_PDFIUM").map(PathBuf::from),
+            browser: var("KV_BROWSER").map(PathBuf::from),
+            project_plugins: env_bool("KV_PROJECT_PLUGINS", var("KV_PROJECT_PLUGINS")),
+        }
+    }
+
+    /// Returns these settings with all values set in `other` replaced.
+    pub fn merge(self, other: Settings) -> Settings {
+        Settings {
+            background: other.background.or(self.background),
+            color: other.color.or(self.color),
+            mode: other.mode.or(self.mode),
+            resize: other.resize.or(self.resize),
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            markdown_theme: other.markdown_theme.or(self.markdown_theme),
+            theme: other.theme.or(self.theme),
+            font: other.font.or(self.font),
+            pdfium: other.pdfium.or(self.pdfium),
+            browser: other.browser.or(self.browser),
+            project_plugins: other.project_plugins.or(self.project_plugins),
+        }
+    }
+}
+
+pub fn get_settings_path() -> PathBuf {
+    kv_project_dirs().config_dir.join("config.toml")
+}
+
+/// Reads a settings file, a missing file yields empty settings.
+pub fn read_settings(path: &Path) -> Result<Settings> {
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str::<Settings>(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Loads the settings of `config.toml`, overridden by the `KV_*` environment variables.
+/// A broken configuration must not make kv unusable, it is ignored with a warning.
+pub fn load_settings() -> Settings {
+    let settings = read_settings(&get_settings_path()).unwrap_or_else(|e| {
+        eprintln!("Warning: Ignoring the configuration: {:#}", e);
+        Settings::default()
+    });
+    settings.merge(Settings::from_env(|name| std::env::var(name).ok()))
+}
+
+/// System-wide configuration directory.
+fn system_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("PROGRAMDATA").map(|dir| PathBuf::from(dir).join("kv"))
+    } else {
+        Some(PathBuf::from("/etc/kv"))
+    }
+}
+
+/// Returns the `*.toml` files of a drop-in directory in name order.
+fn drop_in_configs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Finds the nearest `.kv/plugins.toml` in `dir` or its parents.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(".kv").join("plugins.toml"))
+        .find(|path| path.is_file())
+}
+
+/// Returns the project config of `cwd` if project plugins are enabled.
+pub fn project_config(options: &PluginOptions, cwd: &Path) -> Option<PathBuf> {
+    options.project.then(|| find_project_config(cwd)).flatten()
+}
 
-    if !config_path.exists() {
-        return HashMap::new();
+/// Returns the plugin configuration files in the order they are applied: system-wide and user
+/// configs each followed by their `plugins.d` drop-ins, the project config and the override.
+/// Plugins of later files replace plugins of earlier files with the same name.
+pub fn plugin_config_paths(options: &PluginOptions) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for dir in [system_config_dir(), Some(kv_project_dirs().config_dir)]
+        .into_iter()
+        .flatten()
+    {
+        let config_path = dir.join("plugins.toml");
+        if config_path.is_file() {
+            paths.push(config_path);
+        }
+        paths.extend(drop_in_configs(&dir.join("plugins.d")));
     }
 
-    match std::fs::read_to_string(&config_path) {
-        Ok(content) => match toml::from_str::<PluginConfig>(&content) {
-            Ok(cfg) => cfg.plugins,
-            Err(e) => {
-                eprintln!("Warning: Failed to parse plugins.toml: {}", e);
-                HashMap::new()
-            }
-        },
-        Err(e) => {
-            eprintln!("Warning: Failed to read plugins.toml: {}", e);
-            HashMap::new()
+    let cwd = std::env::current_dir().ok();
+    paths.extend(cwd.and_then(|cwd| project_config(options, &cwd)));
+
+    // an explicit config must exist, a missing file is reported
+    paths.extend(options.config.clone());
+    paths
+}
+
+/// Reads a plugins configuration file, parse errors include the line and column.
+pub fn read_plugin_config(path: &Path) -> Result<HashMap<String, Plugin>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let config = toml::from_str::<PluginConfig>(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+    Ok(config.plugins)
+}
+
+/// Reads all plugin configuration files, later files override plugins by name.
+pub fn read_plugin_configs(paths: &[PathBuf]) -> Result<HashMap<String, Plugin>> {
+    let mut plugins = HashMap::new();
+    for path in paths {
+        plugins.extend(read_plugin_config(path)?);
+    }
+    Ok(plugins)
+}
+
+/// Loads all plugin configuration files, skipping invalid files with a warning.
+pub fn load_plugins(options: &PluginOptions) -> HashMap<String, Plugin> {
+    let mut plugins = HashMap::new();
+    for path in plugin_config_paths(options) {
+        match read_plugin_config(&path) {
+            Ok(layer) => plugins.extend(layer),
+            Err(e) => eprintln!(
+                "Warning: {:#}\nPlugins of this file are disabled, run `kv plugins check` for details.",
+                e
+            ),
         }
     }
+    plugins
+}
+
+/// Finds a program like a shell does, in `PATH` unless it contains a directory.
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            path.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        }
+        #[cfg(not(unix))]
+        path.is_file()
+    };
+
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+
+    let suffixes: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| {
+            suffixes
+                .iter()
+                .map(move |suffix| dir.join(format!("{}{}", program, suffix)))
+        })
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Orders plugins by descending priority, then by name, so the same plugin wins every run.
+pub fn sort_plugins(plugins: HashMap<String, Plugin>) -> Vec<(String, Plugin)> {
+    let mut plugins: Vec<_> = plugins.into_iter().collect();
+    plugins.sort_by(|(a_name, a), (b_name, b)| {
+        b.priority.cmp(&a.priority).then_with(|| a_name.cmp(b_name))
+    });
+    plugins
 }
 
 pub fn has_extension_or_magic_bytes(
@@ -104,10 +501,11 @@ pub fn has_extension_or_magic_bytes(
 ) -> bool {
     for hex_str in magic_hex_list {
         // remove spaces and convert hex string to byte vector
-        if let Ok(magic) = hex::decode(hex_str.replace(" ", "")) {
-            if data.len() >= magic.len() && &data[0..magic.len()] == magic.as_slice() {
-                return true;
-            }
+        if let Ok(magic) = hex::decode(hex_str.replace(" ", ""))
+            && data.len() >= magic.len()
+            && &data[0..magic.len()] == magic.as_slice()
+        {
+            return true;
         }
     }
 
@@ -133,7 +531,8 @@ pub fn open_config() -> Result<()> {
     if !path.exists() {
         let template = r#"# KV plugins configuration
 #
-# Define custom handlers for file extensions.
+# Define custom handlers for file formats. A plugin handles an input if any of its
+# extensions, magic-bytes, magic, mime-types, globs or header-regex matches.
 #
 # Example: Convert .xml files to SVG using a CLI tool
 # [xml-converter]
@@ -148,8 +547,68 @@ pub fn open_config() -> Result<()> {
 # [custom-binary] # unique name
 # extensions = ["bin"] # leading dots will be ignored
 # magic-bytes = ["CA FE BA BE"] # spaces will be ignored
-# output = "image" # one of: image, pdf, office, svg, or text
+# output = "image" # one of: auto, image, pdf, office, svg, html, markdown, text, or hex
 # path = "my-converter" # correctly quoted path
+#
+# Example: Match by content and file name
+# [custom-data]
+# magic = [{ offset = 4, bytes = "44 41 54 41" }] # magic bytes after a 4 byte header
+# mime-types = ["application/vnd.sqlite3", "video/*"] # sniffed from the content
+# globs = ["*.tar.gz", "Dockerfile"] # matched against the file name and path
+# header-regex = "^#!.*python" # matched against the first 4096 bytes
+# output = "text"
+# path = "my-inspector"
+#
+# Example: Only convert SVG files the built-in renderer fails on
+# [svg-fallback]
+# extensions = ["svg"]
+# output = "image"
+# path = "rsvg-convert"
+# mode = "fallback" # Optional: "override" (default) runs instead of the built-in handlers
+# priority = 10 # Optional: plugins with a higher priority are tried first (default 0)
+#
+# Example: Run a converter with settings
+# [eps-converter]
+# extensions = ["eps"]
+# output = "image"
+# path = "gs -q -dSAFER -dBATCH -dNOPAUSE -sDEVICE=pngalpha -dEPSCrop -sOutputFile={O} {}"
+# placeholder = "{}"
+# output-placeholder = "{O}"
+# timeout = 30 # Optional: seconds until the plugin is killed (unlimited by default)
+# env = { GS_LIB = "/opt/gs/lib" } # Optional: additional environment variables
+# cwd = "/tmp" # Optional: working directory
+# stdin-closed = true # Optional: close stdin when the input is passed as file
+#
+# Example: Extract video frames into a directory, shown as pages (-P, -A)
+# [video-frames]
+# extensions = ["mp4", "mkv"]
+# output = "image"
+# path = "ffmpeg -loglevel error -i {} -vf fps=1/10 -frames:v 6 {O}/%03d.png"
+# placeholder = "{}"
+# output-placeholder = "{O}"
+# output-dir = true # Optional: the output placeholder is a directory of images
+#
+# Example: Convert Keynote presentations with LibreOffice, matched by the zip entries of
+# their content instead of the extension, as ".key" files are usually private keys
+# [keynote]
+# header-regex = '(?s-u)^PK\x03\x04.{26}Index/(Document|Slide|MasterSlide)'
+# output = "pdf"
+# path = "soffice --headless --convert-to pdf --outdir {O} {}"
+# placeholder = "{}"
+# output-placeholder = "{O}"
+# output-dir = true # the converted file is named after the input
+#
+# Example: Print a colored hex dump
+# [hex]
+# extensions = ["bin"]
+# output = "text" # printed like text files
+# path = "hexyl --color always"
+#
+# Placeholders in `path` are replaced with the render context:
+# {width}, {height}: requested size in pixels (terminal size unless set with -w or -H)
+# {pages}: selected pages as "1,2,3" (empty for all pages), {page}: first selected page
+# {bg}: background color as hex string, e.g. #FFFFFF (empty without background)
+# {ext}: file extension, {filename}: file name (both empty for stdin)
 "#;
         std::fs::write(&path, template).context("Failed to create plugins.toml")?;
         eprintln!("Created default config file at: {}", path.display());
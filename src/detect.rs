@@ -0,0 +1,527 @@
+use anyhow::Result;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// Extensions of documents converted to PDF with LibreOffice.
+#[rustfmt::skip]
+pub const OFFICE_EXTENSIONS: &[&str] = &[
+    // text documents
+    "doc", "docx", "docm", "dot", "dotx", "odt", "ott", "fodt", "rtf", "pages",
+    // spreadsheets
+    "xls", "xlsx", "xlsm", "xlsb", "ods", "ots", "fods", "numbers",
+    // presentations
+    "ppt", "pptx", "pptm", "pps", "ppsx", "odp", "otp", "fodp",
+    // drawings
+    "odg", "otg", "vsd", "vsdx",
+];
+
+/// Extensions of spreadsheets, exported with one PDF page per sheet.
+pub const SPREADSHEET_EXTENSIONS: &[&str] = &[
+    "xls", "xlsx", "xlsm", "xlsb", "ods", "ots", "fods", "numbers", "csv", "tsv",
+];
+
+/// Extensions of presentations, exported with one PDF page per slide.
+pub const PRESENTATION_EXTENSIONS: &[&str] =
+    &["ppt", "pptx", "pptm", "pps", "ppsx", "odp", "otp", "fodp"];
+
+const OLE2_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Main content types of OOXML documents (listed in `[Content_Types].xml`).
+const OOXML_CONTENT_TYPES: &[(&str, &str)] = &[
+    ("wordprocessingml.document.main+xml", "docx"),
+    ("wordprocessingml.template.main+xml", "dotx"),
+    ("ms-word.document.macroEnabled.main+xml", "docm"),
+    ("spreadsheetml.sheet.main+xml", "xlsx"),
+    ("ms-excel.sheet.macroEnabled.main+xml", "xlsm"),
+    ("ms-excel.sheet.binary.macroEnabled.main", "xlsb"),
+    ("presentationml.presentation.main+xml", "pptx"),
+    ("presentationml.slideshow.main+xml", "ppsx"),
+    ("ms-powerpoint.presentation.macroEnabled.main+xml", "pptm"),
+    ("ms-visio.drawing.main+xml", "vsdx"),
+];
+
+/// Mime types of ODF documents (stored in the `mimetype` entry).
+const ODF_MIME_TYPES: &[(&str, &str)] = &[
+    ("application/vnd.oasis.opendocument.text-template", "ott"),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet-template",
+        "ots",
+    ),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+    (
+        "application/vnd.oasis.opendocument.presentation-template",
+        "otp",
+    ),
+    ("application/vnd.oasis.opendocument.presentation", "odp"),
+    (
+        "application/vnd.oasis.opendocument.graphics-template",
+        "otg",
+    ),
+    ("application/vnd.oasis.opendocument.graphics", "odg"),
+];
+
+/// Names of the main stream of legacy (OLE2) office documents in their root storage.
+const OLE2_STREAMS: &[(&str, &str)] = &[
+    ("WordDocument", "doc"),
+    ("Workbook", "xls"),
+    ("Book", "xls"),
+    ("PowerPoint Document", "ppt"),
+    ("VisioDocument", "vsd"),
+];
+
+fn read_zip_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content).ok()?;
+    Some(content)
+}
+
+fn detect_zip_format(data: &[u8]) -> Option<&'static str> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+
+    if let Some(mime_type) = read_zip_entry(&mut archive, "mimetype") {
+        let mime_type = mime_type.trim();
+        return ODF_MIME_TYPES
+            .iter()
+            .find(|(mime, _)| *mime == mime_type)
+            .map(|(_, extension)| *extension);
+    }
+
+    let content_types = read_zip_entry(&mut archive, "[Content_Types].xml")?;
+    OOXML_CONTENT_TYPES
+        .iter()
+        .find(|(content_type, _)| content_types.contains(content_type))
+        .map(|(_, extension)| *extension)
+}
+
+/// Sector ids above this mark free sectors and the end of chains.
+const OLE2_MAX_SECTOR: u32 = 0xFFFF_FFFA;
+/// Entries that do not point to a sibling or child in the directory tree.
+const OLE2_NO_ENTRY: u32 = 0xFFFF_FFFF;
+const OLE2_STREAM_ENTRY: u8 = 2;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// A compound file (OLE2), the container of legacy office documents.
+struct Ole2File<'a> {
+    data: &'a [u8],
+    sector_size: usize,
+    /// Sectors of the file allocation table, which chains the sectors of streams.
+    fat_sectors: Vec<u32>,
+}
+
+impl<'a> Ole2File<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let sector_shift = read_u16(data, 0x1E)?;
+        if !(9..=12).contains(&sector_shift) {
+            return None;
+        }
+        let mut file = Self {
+            data,
+            sector_size: 1 << sector_shift,
+            fat_sectors: Vec::new(),
+        };
+
+        // the first 109 FAT sectors are listed in the header, the rest in a chain of DIFAT
+        // sectors ending with the id of the next one
+        let mut fat_sectors: Vec<u32> = (0..109)
+            .map_while(|i| read_u32(data, 0x4C + i * 4))
+            .collect();
+        let mut difat_sector = read_u32(data, 0x44)?;
+        for _ in 0..read_u32(data, 0x48)? {
+            let Some(sector) = file.sector(difat_sector) else {
+                break;
+            };
+            let (entries, next) = sector.split_at(file.sector_size - 4);
+            fat_sectors.extend(entries.chunks(4).filter_map(|id| read_u32(id, 0)));
+            difat_sector = read_u32(next, 0)?;
+        }
+        fat_sectors.retain(|&id| id < OLE2_MAX_SECTOR);
+        file.fat_sectors = fat_sectors;
+        Some(file)
+    }
+
+    fn sector(&self, id: u32) -> Option<&'a [u8]> {
+        if id >= OLE2_MAX_SECTOR {
+            return None;
+        }
+        // the header fills the sector before sector 0
+        let start = (id as usize + 1).checked_mul(self.sector_size)?;
+        self.data.get(start..start.checked_add(self.sector_size)?)
+    }
+
+    fn next_sector(&self, id: u32) -> Option<u32> {
+        let ids_per_sector = self.sector_size / 4;
+        let fat_sector = self.sector(*self.fat_sectors.get(id as usize / ids_per_sector)?)?;
+        read_u32(fat_sector, id as usize % ids_per_sector * 4)
+    }
+
+    /// Reads the directory, the chain of sectors starting at the one named in the header.
+    fn directory(&self) -> Vec<u8> {
+        let mut directory = Vec::new();
+        let mut id = read_u32(self.data, 0x30);
+        // a corrupt chain may loop, no chain is longer than the file
+        for _ in 0..self.data.len() / self.sector_size {
+            let Some(sector) = id.and_then(|id| self.sector(id)) else {
+                break;
+            };
+            directory.extend_from_slice(sector);
+            id = id.and_then(|id| self.next_sector(id));
+        }
+        directory
+    }
+
+    /// Lists the names of the streams in the root storage, stored in a tree of siblings below
+    /// the root entry.
+    fn root_streams(&self) -> Vec<String> {
+        let directory = self.directory();
+        let entries: Vec<&[u8]> = directory.chunks_exact(128).collect();
+        let mut streams = Vec::new();
+        let mut pending: Vec<u32> = entries
+            .first()
+            .and_then(|root| read_u32(root, 0x4C))
+            .into_iter()
+            .collect();
+        let mut visited = vec![false; entries.len()];
+        while let Some(id) = pending.pop() {
+            let Some(entry) = entries.get(id as usize) else {
+                continue;
+            };
+            if std::mem::replace(&mut visited[id as usize], true) {
+                continue;
+            }
+            pending.extend(
+                [read_u32(entry, 0x44), read_u32(entry, 0x48)]
+                    .into_iter()
+                    .flatten()
+                    .filter(|&sibling| sibling != OLE2_NO_ENTRY),
+            );
+
+            if entry[0x42] == OLE2_STREAM_ENTRY {
+                // NUL-terminated UTF-16LE, the length includes the terminator
+                let length = read_u16(entry, 0x40).unwrap_or(0).clamp(2, 64) as usize - 2;
+                let name: Vec<u16> = entry[..length]
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .collect();
+                streams.push(String::from_utf16_lossy(&name));
+            }
+        }
+        streams
+    }
+}
+
+fn detect_ole2_format(data: &[u8]) -> Option<&'static str> {
+    let streams = Ole2File::parse(data)?.root_streams();
+    // names are compared case-insensitively in compound files
+    OLE2_STREAMS
+        .iter()
+        .find(|(stream, _)| streams.iter().any(|name| name.eq_ignore_ascii_case(stream)))
+        .map(|(_, extension)| *extension)
+}
+
+/// Detects office documents by content and returns a matching file extension.
+pub fn detect_office_format(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"PK\x03\x04") {
+        detect_zip_format(data)
+    } else if data.starts_with(OLE2_MAGIC) {
+        detect_ole2_format(data)
+    } else if data.starts_with(b"{\\rtf") {
+        Some("rtf")
+    } else {
+        None
+    }
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Returns all `element` elements in document order, from their start tag up to their end
+/// tag (or the next element for empty ones).
+fn xml_elements<'a>(xml: &'a str, element: &str) -> Vec<&'a str> {
+    let end_tag = format!("</{}>", element);
+    let starts: Vec<usize> = xml
+        .match_indices(&format!("<{} ", element))
+        .map(|(start, _)| start)
+        .collect();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(xml.len());
+            let content = &xml[start..end];
+            &content[..content.find(&end_tag).unwrap_or(content.len())]
+        })
+        .collect()
+}
+
+/// Reads an attribute of the start tag of an element.
+fn xml_attribute(element: &str, attribute: &str) -> Option<String> {
+    let tag = &element[..element.find('>').unwrap_or(element.len())];
+    let attribute_start = format!(" {}=\"", attribute);
+    let value = &tag[tag.find(&attribute_start)? + attribute_start.len()..];
+    Some(unescape_xml(&value[..value.find('"')?]))
+}
+
+/// Reads the names of the visible sheets of an OOXML workbook with cells or drawings.
+fn ooxml_sheet_names(archive: &mut ZipArchive<Cursor<&[u8]>>, workbook: &str) -> Vec<String> {
+    let relationships = read_zip_entry(archive, "xl/_rels/workbook.xml.rels").unwrap_or_default();
+    let relationships = xml_elements(&relationships, "Relationship");
+
+    xml_elements(workbook, "sheet")
+        .into_iter()
+        .filter(|sheet| xml_attribute(sheet, "state").is_none_or(|state| state == "visible"))
+        .filter(|sheet| {
+            // sheets whose worksheet cannot be read are kept
+            let target = xml_attribute(sheet, "r:id").and_then(|id| {
+                relationships
+                    .iter()
+                    .find(|relationship| xml_attribute(relationship, "Id").as_ref() == Some(&id))
+                    .and_then(|relationship| xml_attribute(relationship, "Target"))
+            });
+            let path = target.map(|target| match target.strip_prefix('/') {
+                Some(absolute) => absolute.to_string(),
+                None => format!("xl/{}", target),
+            });
+            path.and_then(|path| read_zip_entry(archive, &path))
+                .is_none_or(|worksheet| {
+                    ["<v>", "<is>", "<drawing "]
+                        .iter()
+                        .any(|content| worksheet.contains(content))
+                })
+        })
+        .filter_map(|sheet| xml_attribute(sheet, "name"))
+        .collect()
+}
+
+/// Reads the names of the visible tables of an ODF spreadsheet with cells or drawings.
+fn odf_sheet_names(content: &str) -> Vec<String> {
+    // tables are hidden by their automatic style
+    let hidden_styles: Vec<String> = xml_elements(content, "style:style")
+        .into_iter()
+        .filter(|style| style.contains("table:display=\"false\""))
+        .filter_map(|style| xml_attribute(style, "style:name"))
+        .collect();
+
+    xml_elements(content, "table:table")
+        .into_iter()
+        .filter(|table| {
+            xml_attribute(table, "table:style-name")
+                .is_none_or(|style| !hidden_styles.contains(&style))
+        })
+        .filter(|table| table.contains("office:value-type=") || table.contains("<draw:"))
+        .filter_map(|table| xml_attribute(table, "table:name"))
+        .collect()
+}
+
+/// Reads the names of the sheets of an OOXML or ODF spreadsheet in tab order. Hidden and empty
+/// sheets are skipped, like in the PDF export with one page per sheet.
+pub fn spreadsheet_sheet_names(data: &[u8]) -> Option<Vec<String>> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+
+    if let Some(workbook) = read_zip_entry(&mut archive, "xl/workbook.xml") {
+        return Some(ooxml_sheet_names(&mut archive, &workbook));
+    }
+
+    let content = read_zip_entry(&mut archive, "content.xml")?;
+    Some(odf_sheet_names(&content))
+}
+
+/// Resolves a sheet name or a 1-indexed sheet number to a 0-indexed sheet. An exact name comes
+/// first, so sheets named like numbers (e.g. "2024") can be selected.
+pub fn resolve_sheet(sheet: &str, names: Option<&[String]>) -> Result<u16> {
+    if let Some(index) = names.and_then(|names| names.iter().position(|name| name == sheet)) {
+        return Ok(index as u16);
+    }
+
+    if let Ok(index) = sheet.trim().parse::<u16>() {
+        if index < 1 {
+            anyhow::bail!("Sheet index must be >= 1");
+        }
+        return Ok(index - 1);
+    }
+
+    let Some(names) = names else {
+        anyhow::bail!("Sheet names are not available, select the sheet by index");
+    };
+    // hidden and empty sheets are not exported, so they cannot be selected
+
+    names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(sheet))
+        .map(|index| index as u16)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Sheet not found: {} (available: {})",
+                sheet,
+                names.join(", ")
+            )
+        })
+}
+
+/// Magic bytes at an offset and the MIME type they identify, checked in order.
+const MIME_MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xFF\xD8\xFF", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"\x00\x00\x01\x00", "image/x-icon"),
+    (8, b"WEBP", "image/webp"),
+    (4, b"ftypheic", "image/heic"),
+    (4, b"ftypheix", "image/heic"),
+    (4, b"ftypmif1", "image/heif"),
+    (4, b"ftypavif", "image/avif"),
+    (4, b"ftypqt", "video/quicktime"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1A\x45\xDF\xA3", "video/x-matroska"),
+    (8, b"AVI ", "video/x-msvideo"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (8, b"WAVE", "audio/wav"),
+    (0, b"%PDF", "application/pdf"),
+    (0, b"%!PS", "application/postscript"),
+    (0, b"{\\rtf", "application/rtf"),
+    (0, b"\x1F\x8B", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xFD7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xB5\x2F\xFD", "application/zstd"),
+    (0, b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"\x7FELF", "application/x-executable"),
+    (0, b"\x00asm", "application/wasm"),
+];
+
+/// MIME types of office documents by their detected extension.
+const OFFICE_MIME_TYPES: &[(&str, &str)] = &[
+    ("doc", "application/msword"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("vsd", "application/vnd.visio"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+];
+
+/// Checks for bytes at an offset of the data.
+pub fn has_magic_at(data: &[u8], offset: usize, magic: &[u8]) -> bool {
+    data.get(offset..offset + magic.len()) == Some(magic)
+}
+
+fn sniff_text_mime(data: &[u8]) -> Option<&'static str> {
+    let header = &data[..data.len().min(1024)];
+    let text = match std::str::from_utf8(header) {
+        Ok(text) => text,
+        // the header may end within a multibyte character
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&header[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    if text.contains('\0') {
+        return None;
+    }
+
+    let start = text.trim_start().to_ascii_lowercase();
+    if start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg")) {
+        Some("image/svg+xml")
+    } else if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        Some("text/html")
+    } else if start.starts_with("<?xml") {
+        Some("application/xml")
+    } else {
+        Some("text/plain")
+    }
+}
+
+/// The content type of an input, sniffed once and passed to everything choosing its handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sniffed {
+    pub mime: &'static str,
+    /// Extension of a detected office document.
+    pub office: Option<&'static str>,
+}
+
+impl Sniffed {
+    pub fn new(data: &[u8]) -> Self {
+        let office = detect_office_format(data);
+        Self {
+            mime: office_mime(data, office),
+            office,
+        }
+    }
+}
+
+/// Sniffs the MIME type of data by its content.
+pub fn sniff_mime(data: &[u8]) -> &'static str {
+    Sniffed::new(data).mime
+}
+
+/// Sniffs the MIME type of data with the office format already detected in it.
+fn office_mime(data: &[u8], office: Option<&'static str>) -> &'static str {
+    if let Some(office) = office {
+        let mime = ODF_MIME_TYPES
+            .iter()
+            .find(|(_, extension)| *extension == office)
+            .map(|(mime, _)| *mime)
+            .or_else(|| {
+                OFFICE_MIME_TYPES
+                    .iter()
+                    .find(|(extension, _)| *extension == office)
+                    .map(|(_, mime)| *mime)
+            });
+        if let Some(mime) = mime {
+            return mime;
+        }
+    }
+
+    if data.starts_with(b"PK\x03\x04") {
+        return "application/zip";
+    }
+    if data.starts_with(OLE2_MAGIC) {
+        return "application/x-ole-storage";
+    }
+
+    MIME_MAGIC
+        .iter()
+        .find(|(offset, magic, _)| has_magic_at(data, *offset, magic))
+        .map(|(_, _, mime)| *mime)
+        .or_else(|| sniff_text_mime(data))
+        .unwrap_or("application/octet-stream")
+}
+
+/// Checks a MIME type against a pattern like `image/png`, `image/*` or `*/*`.
+pub fn mime_matches(mime: &str, pattern: &str) -> bool {
+    match pattern.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((kind, "*")) => mime
+            .split_once('/')
+            .is_some_and(|(mime_kind, _)| mime_kind.eq_ignore_ascii_case(kind)),
+        _ => mime.eq_ignore_ascii_case(pattern),
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 16;
+const RESET: &str = "\x1b[0m";
+
+#[cfg(test)]
+mod tests_hexdump;
+
+/// Class of a byte, each class is shown in its own color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteClass {
+    Null,
+    Printable,
+    Whitespace,
+    Control,
+    NonAscii,
+}
+
+impl ByteClass {
+    pub fn of(byte: u8) -> Self {
+        match byte {
+            0 => ByteClass::Null,
+            b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c => ByteClass::Whitespace,
+            _ if byte.is_ascii_graphic() => ByteClass::Printable,
+            _ if byte.is_ascii() => ByteClass::Control,
+            _ => ByteClass::NonAscii,
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            ByteClass::Null => "\x1b[90m",
+            ByteClass::Printable => "\x1b[36m",
+            ByteClass::Whitespace => "\x1b[32m",
+            ByteClass::Control => "\x1b[35m",
+            ByteClass::NonAscii => "\x1b[33m",
+        }
+    }
+
+    /// Character of the byte in the text column.
+    fn symbol(self, byte: u8) -> char {
+        match self {
+            ByteClass::Printable => byte as char,
+            ByteClass::Whitespace if byte == b' ' => ' ',
+            _ => '.',
+        }
+    }
+}
+
+/// Parses an offset, decimal or hexadecimal with a `0x` prefix.
+fn parse_offset(offset: &str) -> Result<usize> {
+    let offset = offset.trim();
+    match offset.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => offset.parse(),
+    }
+    .with_context(|| format!("Invalid byte offset: {:?}", offset))
+}
+
+/// Parses a byte range: "START:END" (END excluded), "START:+LENGTH", "START:" or ":END".
+pub fn parse_byte_range(range: &str) -> Result<(usize, usize)> {
+    let Some((start, end)) = range.split_once(':') else {
+        anyhow::bail!("Invalid byte range: {:?} (expected START:END)", range);
+    };
+    let start = if start.is_empty() {
+        0
+    } else {
+        parse_offset(start)?
+    };
+    let end = match end.strip_prefix('+') {
+        Some(length) => start.saturating_add(parse_offset(length)?),
+        None if end.is_empty() => usize::MAX,
+        None => parse_offset(end)?,
+    };
+    if start >= end {
+        anyhow::bail!("Invalid byte range: {:?}", range);
+    }
+    Ok((start, end))
+}
+
+fn write_line(out: &mut String, line: &[u8], offset: usize, color: bool) {
+    let paint = |out: &mut String, class: ByteClass| {
+        if color {
+            out.push_str(class.color());
+        }
+    };
+    let reset = |out: &mut String| {
+        if color {
+            out.push_str(RESET);
+        }
+    };
+
+    let _ = write!(out, "{:08x} ", offset);
+    for i in 0..BYTES_PER_LINE {
+        if i % 8 == 0 {
+            out.push(' ');
+        }
+        match line.get(i) {
+            Some(&byte) => {
+                paint(out, ByteClass::of(byte));
+                let _ = write!(out, "{:02x}", byte);
+                reset(out);
+                out.push(' ');
+            }
+            None => out.push_str("   "),
+        }
+    }
+
+    out.push_str(" |");
+    for &byte in line {
+        let class = ByteClass::of(byte);
+        paint(out, class);
+        out.push(class.symbol(byte));
+        reset(out);
+    }
+    out.push_str("|\n");
+}
+
+/// Formats data like `hexdump -C`: offsets starting at `offset`, two groups of eight bytes
+/// and the printable characters. Repeated lines are squeezed into a single `*`.
+pub fn hex_dump(data: &[u8], offset: usize, color: bool) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut squeezed = false;
+
+    for (i, line) in data.chunks(BYTES_PER_LINE).enumerate() {
+        if previous == Some(line) && line.len() == BYTES_PER_LINE {
+            if !squeezed {
+                out.push_str("*\n");
+                squeezed = true;
+            }
+            continue;
+        }
+        write_line(&mut out, line, offset + i * BYTES_PER_LINE, color);
+        previous = Some(line);
+        squeezed = false;
+    }
+
+    let _ = writeln!(out, "{:08x}", offset + data.len());
+    out
+}
@@ -0,0 +1,55 @@
+use super::*;
+use rstest::rstest;
+
+#[rstest]
+#[case(0x00, ByteClass::Null)]
+#[case(b'a', ByteClass::Printable)]
+#[case(b' ', ByteClass::Whitespace)]
+#[case(b'\n', ByteClass::Whitespace)]
+#[case(0x1b, ByteClass::Control)]
+#[case(0x89, ByteClass::NonAscii)]
+fn test_byte_class(#[case] byte: u8, #[case] expected: ByteClass) {
+    assert_eq!(ByteClass::of(byte), expected);
+}
+
+#[rstest]
+#[case("16:32", Some((16, 32)))]
+#[case("0x10:0x20", Some((16, 32)))]
+#[case("16:+16", Some((16, 32)))]
+#[case(":32", Some((0, 32)))]
+#[case("16:", Some((16, usize::MAX)))]
+#[case("32:16", None)]
+#[case("16", None)]
+#[case("x:16", None)]
+fn test_parse_byte_range(#[case] range: &str, #[case] expected: Option<(usize, usize)>) {
+    assert_eq!(parse_byte_range(range).ok(), expected);
+}
+
+#[test]
+fn test_hex_dump() {
+    let dump = hex_dump(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR!", 0, false);
+    assert_eq!(
+        dump,
+        "00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+         00000010  21                                                |!|\n\
+         00000011\n"
+    );
+}
+
+#[test]
+fn test_hex_dump_squeeze() {
+    let dump = hex_dump(&[0; 64], 0x100, false);
+    assert_eq!(
+        dump,
+        "00000100  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+         *\n\
+         00000140\n"
+    );
+}
+
+#[test]
+fn test_hex_dump_color() {
+    let dump = hex_dump(b"a\0", 0, true);
+    assert!(dump.contains("\x1b[36m61\x1b[0m"));
+    assert!(dump.contains("\x1b[90m00\x1b[0m"));
+}
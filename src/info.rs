@@ -0,0 +1,398 @@
+use anyhow::{Context, Result};
+use image::{ColorType, ImageDecoder, ImageFormat, ImageReader};
+use libheif_rs::HeifContext;
+use libheif_rs::integration::image::register_all_decoding_hooks;
+use std::fmt::Write;
+use std::io::Cursor;
+
+use crate::{KvContext, format_size, pdf_page_sizes, sniff_mime};
+
+#[cfg(test)]
+mod tests_info;
+
+/// Position of a photo in decimal degrees, negative in the south and west.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Properties of an input shown by `--info`, unknown values are `None`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct MediaInfo {
+    pub name: String,
+    pub format: String,
+    pub mime: String,
+    /// Pixels of images, points of PDF pages.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Channels, e.g. "rgb" or "gray+alpha".
+    pub color_type: Option<String>,
+    /// Bits per channel.
+    pub bit_depth: Option<u16>,
+    /// Animation frames of images, pages of PDF documents.
+    pub frames: Option<usize>,
+    /// Description of the embedded ICC color profile.
+    pub icc_profile: Option<String>,
+    pub camera: Option<String>,
+    pub date: Option<String>,
+    pub gps: Option<GpsPosition>,
+    pub file_size: u64,
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+/// Reads the description tag of an ICC profile, in ICC v2 (`desc`) or v4 (`mluc`) format.
+pub fn icc_profile_name(profile: &[u8]) -> Option<String> {
+    // the tag table follows the 128 byte header, 12 bytes per tag
+    let count = be_u32(profile, 128)?.min(profile.len() / 12);
+    let (offset, size) = (0..count).find_map(|i| {
+        let entry = 132 + i * 12;
+        if profile.get(entry..entry + 4)? != b"desc" {
+            return None;
+        }
+        Some((be_u32(profile, entry + 4)?, be_u32(profile, entry + 8)?))
+    })?;
+    let tag = profile.get(offset..offset.checked_add(size)?)?;
+
+    let name = match tag.get(..4)? {
+        b"desc" => {
+            let length = be_u32(tag, 8)?;
+            String::from_utf8_lossy(tag.get(12..12_usize.checked_add(length)?)?).into_owned()
+        }
+        b"mluc" => {
+            // records of language, country, length and offset, the English one if present
+            let record_size = be_u32(tag, 12)?;
+            if record_size < 12 {
+                return None;
+            }
+            let records = be_u32(tag, 8)?.min(tag.len() / 12);
+            let record = (0..records)
+                .map(|i| 16 + i * record_size)
+                .take_while(|&record| record + 12 <= tag.len())
+                .max_by_key(|&record| &tag[record..record + 2] == b"en")?;
+            let length = be_u32(tag, record + 4)?;
+            let start = be_u32(tag, record + 8)?;
+            let utf16: Vec<u16> = tag
+                .get(start..start.checked_add(length)?)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16)
+        }
+        _ => return None,
+    };
+
+    let name = name.trim_end_matches('\0').trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn ascii_field(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let value = String::from_utf8_lossy(values.first()?);
+    let value = value.trim_end_matches('\0').trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Converts degrees, minutes and seconds to decimal degrees, negative for `S` and `W`.
+pub fn gps_coordinate(dms: &[exif::Rational], reference: char) -> Option<f64> {
+    let [degrees, minutes, seconds] = dms else {
+        return None;
+    };
+    let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+    if !value.is_finite() {
+        return None;
+    }
+    Some(if matches!(reference, 'S' | 'W') {
+        -value
+    } else {
+        value
+    })
+}
+
+fn gps_field(exif: &exif::Exif, tag: exif::Tag, ref_tag: exif::Tag) -> Option<f64> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let exif::Value::Rational(dms) = &field.value else {
+        return None;
+    };
+    let reference = ascii_field(exif, ref_tag)?.chars().next()?;
+    gps_coordinate(dms, reference)
+}
+
+/// Adds the camera, capture date and position of the EXIF data of an image.
+fn read_exif(info: &mut MediaInfo, data: &[u8]) {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return;
+    };
+
+    let make = ascii_field(&exif, exif::Tag::Make);
+    let model = ascii_field(&exif, exif::Tag::Model);
+    info.camera = match (make, model) {
+        // models often repeat the make, e.g. "Canon" and "Canon EOS 5D"
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+
+    // "YYYY:MM:DD HH:MM:SS"
+    info.date = ascii_field(&exif, exif::Tag::DateTimeOriginal)
+        .or_else(|| ascii_field(&exif, exif::Tag::DateTime))
+        .map(|date| match date.split_once(' ') {
+            Some((day, time)) => format!("{} {}", day.replace(':', "-"), time),
+            None => date.replace(':', "-"),
+        });
+
+    let latitude = gps_field(&exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef);
+    let longitude = gps_field(&exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef);
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        info.gps = Some(GpsPosition {
+            latitude,
+            longitude,
+        });
+    }
+}
+
+fn color_type_name(color_type: ColorType) -> &'static str {
+    match (color_type.has_color(), color_type.has_alpha()) {
+        (false, false) => "gray",
+        (false, true) => "gray+alpha",
+        (true, false) => "rgb",
+        (true, true) => "rgba",
+    }
+}
+
+/// Skips a chain of GIF data sub-blocks, returning the position after its terminator.
+fn skip_gif_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+/// Counts the image descriptors of a GIF, the complete ones of a truncated file.
+fn gif_frames(data: &[u8]) -> usize {
+    // the global color table follows the header and logical screen descriptor
+    let color_table = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 0x07) + 1) } else { 0 };
+    let Some(&flags) = data.get(10) else {
+        return 0;
+    };
+    let mut pos = 13 + color_table(flags);
+    let mut frames = 0;
+    loop {
+        let next = match data.get(pos) {
+            Some(0x2C) => data.get(pos + 9).and_then(|&flags| {
+                // the image data starts with the LZW minimum code size
+                let next = skip_gif_blocks(data, pos + 11 + color_table(flags))?;
+                frames += 1;
+                Some(next)
+            }),
+            Some(0x21) => skip_gif_blocks(data, pos + 2),
+            _ => None,
+        };
+        match next {
+            Some(next) => pos = next,
+            None => return frames,
+        }
+    }
+}
+
+/// Reads the frame count of the `acTL` chunk of an APNG, 1 for still PNGs.
+fn apng_frames(data: &[u8]) -> usize {
+    let mut pos = 8;
+    while let Some(len) = be_u32(data, pos) {
+        match data.get(pos + 4..pos + 8) {
+            Some(b"acTL") => return be_u32(data, pos + 8).unwrap_or(1).max(1),
+            // the animation control chunk must precede the image data
+            Some(b"IDAT") | None => break,
+            _ => {}
+        }
+        let Some(next) = pos.checked_add(len).and_then(|pos| pos.checked_add(12)) else {
+            break;
+        };
+        pos = next;
+    }
+    1
+}
+
+/// Counts the `ANMF` chunks of an animated WebP, 1 for still images.
+fn webp_frames(data: &[u8]) -> usize {
+    // chunks follow the RIFF header, padded to an even size
+    let mut pos: usize = 12;
+    let mut frames = 0;
+    while let Some(len) = pos.checked_add(4).and_then(|offset| le_u32(data, offset)) {
+        if &data[pos..pos + 4] == b"ANMF" {
+            frames += 1;
+        }
+        let Some(next) = (pos + 8).checked_add(len + (len & 1)) else {
+            break;
+        };
+        pos = next;
+    }
+    frames.max(1)
+}
+
+/// Counts the frames of animated images from their container, without decoding them.
+fn count_frames(data: &[u8], format: ImageFormat) -> usize {
+    match format {
+        ImageFormat::Gif => gif_frames(data),
+        ImageFormat::Png => apng_frames(data),
+        ImageFormat::WebP => webp_frames(data),
+        _ => 1,
+    }
+}
+
+fn read_image_info(info: &mut MediaInfo, data: &[u8]) -> Result<()> {
+    register_all_decoding_hooks();
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .context("Failed to guess image format")?;
+    let Some(format) = reader.format() else {
+        return Ok(());
+    };
+    info.format = format!("{:?}", format).to_uppercase();
+
+    let mut decoder = reader
+        .into_decoder()
+        .context("Failed to read image header")?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    info.width = Some(width);
+    info.height = Some(height);
+    info.color_type = Some(color_type_name(color_type).to_string());
+    info.bit_depth = Some(color_type.bits_per_pixel() / color_type.channel_count() as u16);
+    info.icc_profile = decoder
+        .icc_profile()
+        .ok()
+        .flatten()
+        .and_then(|profile| icc_profile_name(&profile));
+    info.frames = Some(count_frames(data, format));
+    read_exif(info, data);
+    Ok(())
+}
+
+fn read_heif_info(info: &mut MediaInfo, data: &[u8]) -> Result<()> {
+    let context = HeifContext::read_from_bytes(data).context("Failed to read HEIF data")?;
+    let handle = context
+        .primary_image_handle()
+        .context("Failed to read HEIF primary image")?;
+    info.width = Some(handle.width());
+    info.height = Some(handle.height());
+    let color_type = if handle.has_alpha_channel() {
+        "rgba"
+    } else {
+        "rgb"
+    };
+    info.color_type = Some(color_type.to_string());
+    info.bit_depth = Some(handle.luma_bits_per_pixel().into());
+    info.frames = Some(context.image_ids().len());
+    info.icc_profile = handle
+        .color_profile_raw()
+        .and_then(|profile| icc_profile_name(&profile.data));
+    read_exif(info, data);
+    Ok(())
+}
+
+fn read_svg_info(info: &mut MediaInfo, data: &[u8]) -> Result<()> {
+    let tree =
+        usvg::Tree::from_data(data, &usvg::Options::default()).context("Failed to parse SVG")?;
+    let size = tree.size().to_int_size();
+    info.width = Some(size.width());
+    info.height = Some(size.height());
+    Ok(())
+}
+
+fn read_pdf_info(ctx: &KvContext, info: &mut MediaInfo, data: &[u8]) -> Result<()> {
+    let sizes = pdf_page_sizes(ctx, data)?;
+    if let Some((width, height)) = sizes.first() {
+        info.width = Some(width.round() as u32);
+        info.height = Some(height.round() as u32);
+    }
+    info.frames = Some(sizes.len());
+    Ok(())
+}
+
+/// Reads the properties of an input without rendering it. Inputs that are neither images nor
+/// PDF documents only get their format and size.
+pub fn media_info(ctx: &KvContext, name: &str, data: &[u8]) -> Result<MediaInfo> {
+    let mime = sniff_mime(data);
+    let mut info = MediaInfo {
+        name: name.to_string(),
+        format: mime.to_string(),
+        mime: mime.to_string(),
+        file_size: data.len() as u64,
+        ..MediaInfo::default()
+    };
+
+    match mime {
+        "image/heic" | "image/heif" => {
+            info.format = mime.trim_start_matches("image/").to_uppercase();
+            read_heif_info(&mut info, data)?;
+        }
+        "image/svg+xml" => {
+            info.format = "SVG".to_string();
+            read_svg_info(&mut info, data)?;
+        }
+        "application/pdf" => {
+            info.format = "PDF".to_string();
+            read_pdf_info(ctx, &mut info, data)?;
+        }
+        _ => read_image_info(&mut info, data)?,
+    }
+    Ok(info)
+}
+
+/// Formats the known properties of an input as aligned lines below its name.
+pub fn format_media_info(info: &MediaInfo) -> String {
+    let mut lines: Vec<(&str, String)> = vec![("format", info.format.clone())];
+    if let (Some(width), Some(height)) = (info.width, info.height) {
+        lines.push(("dimensions", format!("{}x{}", width, height)));
+    }
+    match (&info.color_type, info.bit_depth) {
+        (Some(color_type), Some(bit_depth)) => {
+            lines.push(("color", format!("{}, {} bit", color_type, bit_depth)))
+        }
+        (Some(color_type), None) => lines.push(("color", color_type.clone())),
+        _ => {}
+    }
+    if let Some(frames) = info.frames {
+        let label = if info.mime == "application/pdf" {
+            "pages"
+        } else {
+            "frames"
+        };
+        lines.push((label, frames.to_string()));
+    }
+    if let Some(icc_profile) = &info.icc_profile {
+        lines.push(("icc profile", icc_profile.clone()));
+    }
+    if let Some(camera) = &info.camera {
+        lines.push(("camera", camera.clone()));
+    }
+    if let Some(date) = &info.date {
+        lines.push(("date", date.clone()));
+    }
+    if let Some(gps) = &info.gps {
+        lines.push(("gps", format!("{:.6}, {:.6}", gps.latitude, gps.longitude)));
+    }
+    lines.push(("file size", format_size(info.file_size)));
+
+    let mut out = format!("{}\n", info.name);
+    for (label, value) in lines {
+        let _ = writeln!(out, "  {:<12} {}", format!("{}:", label), value);
+    }
+    out
+}
@@ -0,0 +1,189 @@
+use super::*;
+use rstest::rstest;
+
+const PNG_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.png");
+const SVG_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.svg");
+const RANDOM_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.random");
+
+/// Builds an ICC profile with only a description tag.
+fn icc_profile(desc: &[u8]) -> Vec<u8> {
+    let mut profile = vec![0; 128];
+    profile.extend_from_slice(&1u32.to_be_bytes());
+    profile.extend_from_slice(b"desc");
+    // the tag data follows the table of one tag
+    profile.extend_from_slice(&144u32.to_be_bytes());
+    profile.extend_from_slice(&(desc.len() as u32).to_be_bytes());
+    profile.extend_from_slice(desc);
+    profile
+}
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+#[test]
+fn test_icc_profile_name_v2() {
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&18u32.to_be_bytes());
+    desc.extend_from_slice(b"sRGB IEC61966-2.1\0");
+    assert_eq!(
+        icc_profile_name(&icc_profile(&desc)).as_deref(),
+        Some("sRGB IEC61966-2.1")
+    );
+}
+
+#[test]
+fn test_icc_profile_name_v4() {
+    let (german, english) = (utf16("Anzeige P3"), utf16("Display P3"));
+    let mut desc = b"mluc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&2u32.to_be_bytes());
+    desc.extend_from_slice(&12u32.to_be_bytes());
+    desc.extend_from_slice(b"deDE");
+    desc.extend_from_slice(&(german.len() as u32).to_be_bytes());
+    desc.extend_from_slice(&40u32.to_be_bytes());
+    desc.extend_from_slice(b"enUS");
+    desc.extend_from_slice(&(english.len() as u32).to_be_bytes());
+    desc.extend_from_slice(&(40 + german.len() as u32).to_be_bytes());
+    desc.extend_from_slice(&german);
+    desc.extend_from_slice(&english);
+    assert_eq!(
+        icc_profile_name(&icc_profile(&desc)).as_deref(),
+        Some("Display P3")
+    );
+}
+
+#[rstest]
+#[case(b"")]
+#[case(&[0; 200])]
+#[case(&icc_profile(b"text\0\0\0\0"))]
+// mluc tags with a record size of 0 and an excessive record count
+#[case(&icc_profile(b"mluc\0\0\0\0\xff\xff\xff\xff\0\0\0\0"))]
+#[case(&icc_profile(b"mluc\0\0\0\0\xff\xff\xff\xff\0\0\0\x0cenUS"))]
+fn test_icc_profile_name_invalid(#[case] profile: &[u8]) {
+    assert_eq!(icc_profile_name(profile), None);
+}
+
+#[rstest]
+#[case('N', 48.858_333)]
+#[case('S', -48.858_333)]
+#[case('W', -48.858_333)]
+fn test_gps_coordinate(#[case] reference: char, #[case] expected: f64) {
+    let dms = [
+        exif::Rational::from((48, 1)),
+        exif::Rational::from((51, 1)),
+        exif::Rational::from((3000, 100)),
+    ];
+    let coordinate = gps_coordinate(&dms, reference).unwrap();
+    assert!((coordinate - expected).abs() < 1e-6);
+    assert_eq!(gps_coordinate(&dms[..2], reference), None);
+}
+
+#[test]
+fn test_media_info_png() {
+    let info = media_info(&KvContext::default(), "test.png", PNG_DATA).unwrap();
+    assert_eq!(info.format, "PNG");
+    assert_eq!(info.mime, "image/png");
+    assert_eq!((info.width, info.height), (Some(1), Some(1)));
+    assert_eq!(info.color_type.as_deref(), Some("rgb"));
+    assert_eq!(info.bit_depth, Some(8));
+    assert_eq!(info.frames, Some(1));
+    assert_eq!(info.file_size, PNG_DATA.len() as u64);
+}
+
+#[rstest]
+#[case(SVG_DATA, "SVG", Some(1))]
+#[case(RANDOM_DATA, "application/octet-stream", None)]
+fn test_media_info_other(
+    #[case] data: &[u8],
+    #[case] expected_format: &str,
+    #[case] expected_width: Option<u32>,
+) {
+    let info = media_info(&KvContext::default(), "input", data).unwrap();
+    assert_eq!(info.format, expected_format);
+    assert_eq!(info.width, expected_width);
+    assert_eq!(info.color_type, None);
+}
+
+/// Encodes an animated GIF of solid 2x2 frames.
+fn animated_gif(frames: u8) -> Vec<u8> {
+    let mut gif = Vec::new();
+    let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+    for i in 0..frames {
+        let buffer = image::RgbaImage::from_pixel(2, 2, image::Rgba([i * 50, 0, 0, 255]));
+        encoder.encode_frame(image::Frame::new(buffer)).unwrap();
+    }
+    drop(encoder);
+    gif
+}
+
+/// Inserts an `acTL` chunk with the given frame count after the IHDR chunk of a PNG.
+fn apng(frames: u32) -> Vec<u8> {
+    let mut png = PNG_DATA[..33].to_vec();
+    png.extend_from_slice(&8u32.to_be_bytes());
+    png.extend_from_slice(b"acTL");
+    png.extend_from_slice(&frames.to_be_bytes());
+    png.extend_from_slice(&0u32.to_be_bytes());
+    // the CRC is not checked when counting
+    png.extend_from_slice(&[0; 4]);
+    png.extend_from_slice(&PNG_DATA[33..]);
+    png
+}
+
+/// Builds a WebP container of the given chunks, their data left empty.
+fn webp(chunks: &[(&[u8], u32)]) -> Vec<u8> {
+    let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+    for (kind, len) in chunks {
+        webp.extend_from_slice(kind);
+        webp.extend_from_slice(&len.to_le_bytes());
+        webp.resize(webp.len() + (len + len % 2) as usize, 0);
+    }
+    webp
+}
+
+#[rstest]
+#[case(animated_gif(1), ImageFormat::Gif, 1)]
+#[case(animated_gif(3), ImageFormat::Gif, 3)]
+#[case(animated_gif(3)[..60].to_vec(), ImageFormat::Gif, 1)] // truncated after the first frame
+#[case(PNG_DATA.to_vec(), ImageFormat::Png, 1)]
+#[case(apng(4), ImageFormat::Png, 4)]
+#[case(webp(&[(b"VP8X", 10), (b"ANIM", 6), (b"ANMF", 17), (b"ANMF", 17)]), ImageFormat::WebP, 2)]
+#[case(webp(&[(b"VP8 ", 11)]), ImageFormat::WebP, 1)]
+// a chunk size beyond the end of the file
+#[case(b"RIFF\0\0\0\0WEBPANMF\xff\xff\xff\xff".to_vec(), ImageFormat::WebP, 1)]
+fn test_count_frames(#[case] data: Vec<u8>, #[case] format: ImageFormat, #[case] expected: usize) {
+    assert_eq!(count_frames(&data, format), expected);
+}
+
+#[test]
+fn test_media_info_animated_gif() {
+    let info = media_info(&KvContext::default(), "anim.gif", &animated_gif(3)).unwrap();
+    assert_eq!(info.format, "GIF");
+    assert_eq!(info.frames, Some(3));
+}
+
+#[test]
+fn test_format_media_info() {
+    let info = MediaInfo {
+        name: "photo.jpg".to_string(),
+        format: "JPEG".to_string(),
+        mime: "image/jpeg".to_string(),
+        width: Some(4032),
+        height: Some(3024),
+        color_type: Some("rgb".to_string()),
+        bit_depth: Some(8),
+        frames: Some(1),
+        camera: Some("Apple iPhone 12".to_string()),
+        gps: Some(GpsPosition {
+            latitude: 48.858_37,
+            longitude: -2.294_481,
+        }),
+        file_size: 2048,
+        ..MediaInfo::default()
+    };
+    assert_eq!(
+        format_media_info(&info),
+        "photo.jpg\n  format:      JPEG\n  dimensions:  4032x3024\n  color:       rgb, 8 bit\n  \
+         frames:      1\n  camera:      Apple iPhone 12\n  gps:         48.858370, -2.294481\n  \
+         file size:   2.0K\n"
+    );
+}
@@ -1,10 +1,10 @@
 use anyhow::{Context, Result};
 use image::{DynamicImage, Rgba};
-use serde;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
+use std::time::Duration;
 
 mod config;
 pub use config::*;
@@ -15,17 +15,46 @@ pub use render::*;
 mod send;
 pub use send::*;
 
+mod process;
+pub use process::*;
+
+mod detect;
+pub use detect::*;
+
+mod cache;
+pub use cache::*;
+
+mod hexdump;
+pub use hexdump::*;
+
+mod info;
+pub use info::*;
+
 #[cfg(test)]
 mod tests_lib;
 
-static PLUGINS: OnceLock<std::collections::HashMap<String, Plugin>> = OnceLock::new();
+/// Configured plugins in the order they are tried.
+static PLUGINS: OnceLock<Vec<PluginEntry>> = OnceLock::new();
 
 #[derive(Debug)]
 pub enum LoadResult {
     Image(DynamicImage),
     Data(Vec<u8>),
+    /// Text produced by a plugin, printed instead of the input.
+    Text(Vec<u8>),
+    /// Formatted terminal output like a hex dump, printed as is.
+    Dump(Vec<u8>),
+    /// A large text file, printed from the file instead of being loaded.
+    TextFile(PathBuf),
 }
 
+/// Inputs larger than this are sniffed by their first bytes, so large text is streamed to the
+/// printer instead of being loaded. Larger image and PDF files are memory-mapped.
+pub const SNIFF_SIZE: usize = 64 * 1024;
+
+/// Bytes shown in hex dumps without a byte range, the colored dump is about 20 times larger.
+pub const DUMP_SIZE: usize = 64 * 1024;
+
 /// Defines how the image should be resized relative to the terminal or explicit dimensions.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResizeMode {
@@ -57,6 +86,96 @@ pub enum CacheMode {
     Custom(PathBuf),
 }
 
+/// Options for rendering HTML with a headless Chrome/Chromium browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Explicit Chrome/Chromium executable, otherwise detected or downloaded.
+    pub browser_path: Option<PathBuf>,
+    /// DevTools websocket URL of an already running browser to use instead of launching one.
+    pub ws_url: Option<String>,
+    /// Allow downloading a Chromium binary if no executable is given.
+    pub allow_download: bool,
+    /// Block all requests that do not target local resources (file, data, blob, about).
+    pub offline: bool,
+    /// Execute JavaScript while rendering.
+    pub javascript: bool,
+    /// Print the page to PDF and render it like a PDF document instead of a screenshot.
+    pub print: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            browser_path: None,
+            ws_url: None,
+            allow_download: true,
+            offline: false,
+            javascript: true,
+            print: false,
+        }
+    }
+}
+
+/// Options for converting office documents with LibreOffice.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OfficeOptions {
+    /// Explicit `soffice` executable, otherwise `soffice` is looked up in `PATH`.
+    pub soffice_path: Option<PathBuf>,
+    /// Abort the conversion if it takes longer, e.g. when LibreOffice hangs on a dialog.
+    pub timeout: Option<Duration>,
+    /// Spreadsheet sheet to render, as 1-indexed number or name.
+    pub sheet: Option<String>,
+    /// Export the notes pages of presentations instead of the slides.
+    pub notes: bool,
+}
+
+/// Options for locating the plugin configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PluginOptions {
+    /// Configuration applied on top of all other configuration files.
+    pub config: Option<PathBuf>,
+    /// Load `.kv/plugins.toml` from the current directory or its parents. Off by default, as
+    /// the commands of a cloned repository must not run just by viewing its files.
+    pub project: bool,
+}
+
+/// Color scheme of rendered markdown documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownTheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// Mirroring of rendered images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+}
+
+/// Orientation of decoded images and transformations of every rendered image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// Apply the orientation stored in the image (EXIF or HEIF transformations).
+    pub orient: bool,
+    /// Clockwise rotation in degrees (0, 90, 180 or 270) of decoded images, rendered documents,
+    /// web pages and plugin outputs, applied after the orientation.
+    pub rotate: u16,
+    /// Mirroring applied after the rotation.
+    pub flip: Option<Flip>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            orient: true,
+            rotate: 0,
+            flip: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum InputType {
@@ -67,6 +186,8 @@ pub enum InputType {
     Pdf,
     Html,
     Office,
+    Markdown,
+    Hex,
 }
 
 #[derive(Debug, Clone)]
@@ -77,7 +198,46 @@ pub struct KvContext {
     pub term_size: (u32, u32),
     pub page_indices: Option<Vec<u16>>,
     pub cache_mode: CacheMode,
+    /// Evict least recently used cache entries beyond this size in bytes.
+    pub cache_max_size: Option<u64>,
     pub background_color: Option<Rgba<u8>>,
+    pub html: HtmlOptions,
+    pub office: OfficeOptions,
+    pub markdown_theme: MarkdownTheme,
+    pub image: ImageOptions,
+    pub plugins: PluginOptions,
+    /// The pdfium library or the directory containing it, otherwise searched in default locations.
+    pub pdfium_path: Option<PathBuf>,
+    /// Byte range (start, end excluded) shown in hex dumps, all bytes if unset.
+    pub byte_range: Option<(usize, usize)>,
+    /// Color hex dumps with ANSI escape codes, unset when they are not shown on a terminal.
+    pub hex_color: bool,
+    /// The file being loaded, if the input does not come from stdin.
+    pub source: Option<PathBuf>,
+}
+
+impl Default for KvContext {
+    /// Detects the input type and clips to the fallback terminal size, without caching.
+    fn default() -> Self {
+        Self {
+            input_type: InputType::Auto,
+            resize_mode: ResizeMode::ClipTerminal,
+            term_size: (800, 400),
+            page_indices: None,
+            cache_mode: CacheMode::Disabled,
+            cache_max_size: None,
+            background_color: None,
+            html: HtmlOptions::default(),
+            office: OfficeOptions::default(),
+            markdown_theme: MarkdownTheme::default(),
+            image: ImageOptions::default(),
+            plugins: PluginOptions::default(),
+            pdfium_path: None,
+            byte_range: None,
+            hex_color: true,
+            source: None,
+        }
+    }
 }
 
 /// Detects terminal size with fallbacks.
@@ -117,6 +277,14 @@ pub fn get_term_size() -> (u32, u32) {
     }
 }
 
+/// Detects the terminal width in columns for text output.
+pub fn get_term_columns() -> Option<usize> {
+    crossterm::terminal::size()
+        .ok()
+        .map(|(columns, _)| columns as usize)
+        .filter(|&columns| columns > 0)
+}
+
 /// Parses a hex string (e.g., "#FFFFFF" or "FFFFFF") into an Rgba color.
 pub fn parse_color(color: &str) -> Result<Rgba<u8>> {
     let hex = color.trim_start_matches('#');
@@ -228,13 +396,95 @@ pub fn parse_pages(pages: &str) -> Result<Option<Vec<u16>>> {
     }
 }
 
-pub fn load_file(ctx: &KvContext, path: &Path) -> Result<LoadResult> {
-    // handle extensions, might fail if non-UTF8
-    let extension = path
-        .extension()
+/// Parse a size with optional binary unit suffix (e.g., "500M", "2G", "1024") to bytes.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim().to_uppercase();
+    let number = size.trim_end_matches(['B', 'I']);
+    let (number, factor) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1u64 << 10),
+        Some('M') => (&number[..number.len() - 1], 1 << 20),
+        Some('G') => (&number[..number.len() - 1], 1 << 30),
+        Some('T') => (&number[..number.len() - 1], 1 << 40),
+        _ => (number, 1),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid size: {}", size))?;
+    if value < 0.0 {
+        anyhow::bail!("Size must not be negative");
+    }
+    Ok((value * factor as f64) as u64)
+}
+
+/// Format a size in bytes with a binary unit suffix (e.g., "1.5M").
+pub fn format_size(size: u64) -> String {
+    let units = ["K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = "B";
+    for next_unit in units {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    if unit == "B" {
+        format!("{}B", size)
+    } else {
+        format!("{:.1}{}", value, unit)
+    }
+}
+
+/// Parse a duration with unit suffix (s, m, h, d or w, e.g., "7d") to a Duration.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let value: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration: {}", duration))?;
+    let seconds = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("Invalid duration unit: {} (use s, m, h, d or w)", unit),
+    };
+    Ok(Duration::from_secs(value * seconds))
+}
+
+/// Format 0-indexed pages as a 1-indexed pages string (e.g., "1,2,3,5").
+pub fn format_pages(indices: &[u16]) -> String {
+    indices
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn is_markdown(ctx: &KvContext, extension: &str) -> bool {
+    ctx.input_type == InputType::Markdown || ["md", "markdown", "mkd"].contains(&extension)
+}
+
+/// Returns the lowercase extension of a path, empty if missing or not UTF-8.
+pub fn file_extension(path: &Path) -> String {
+    path.extension()
         .and_then(|e| e.to_str())
         .unwrap_or("")
-        .to_lowercase();
+        .to_lowercase()
+}
+
+pub fn load_file(ctx: &KvContext, path: &Path) -> Result<LoadResult> {
+    let ctx = &KvContext {
+        source: Some(path.to_path_buf()),
+        ..ctx.clone()
+    };
+
+    let extension = file_extension(path);
 
     {
         // string conversion for URL check
@@ -248,32 +498,334 @@ pub fn load_file(ctx: &KvContext, path: &Path) -> Result<LoadResult> {
 
     let mut file =
         File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let mut map = false;
+    if file.metadata()?.len() > SNIFF_SIZE as u64 {
+        let mut header = vec![0; SNIFF_SIZE];
+        file.read_exact(&mut header)?;
+        if is_streamable_text(ctx, &header, &extension) {
+            return Ok(LoadResult::TextFile(path.to_path_buf()));
+        }
+        map = is_decoded_in_place(ctx, &header, &extension);
+    }
+
+    let data = read_file(file, map)?;
+    load_data(ctx, &data, &extension)
+}
+
+/// Checks if a large input goes to the image or PDF decoders, which read it in place. Only those
+/// inputs are memory-mapped: a map of a file truncated while it is read crashes the process, so
+/// inputs copied elsewhere (e.g. to plugins or LibreOffice) are read instead.
+fn is_decoded_in_place(ctx: &KvContext, header: &[u8], extension: &str) -> bool {
+    match select_handler(ctx, configured_plugins(&ctx.plugins), header, extension) {
+        Handler::Builtin {
+            input_type: InputType::Image | InputType::Pdf,
+            ..
+        } => true,
+        Handler::Builtin {
+            input_type: InputType::Auto,
+            ..
+        } => image::guess_format(header).is_ok(),
+        _ => false,
+    }
+}
+
+/// Contents of a file, memory-mapped if it is large and decoded in place.
+pub enum FileData {
+    Read(Vec<u8>),
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileData::Read(data) => data,
+            FileData::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// Reads a file from its start. Large files are memory-mapped instead of copied into memory if
+/// `map` is set, only for decoders that do not keep the data.
+pub fn read_file(mut file: File, map: bool) -> Result<FileData> {
+    if map && file.metadata()?.len() > SNIFF_SIZE as u64 {
+        // SAFETY: the map is read-only, the file must not be truncated while it is loaded
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.context("Failed to map file")?;
+        return Ok(FileData::Mapped(mmap));
+    }
+    file.rewind()?;
     let mut data = Vec::new();
     file.read_to_end(&mut data)?;
+    Ok(FileData::Read(data))
+}
 
-    load_data(ctx, &data, &extension)
+/// Reads a file that is not streamed, e.g. to inspect it, memory-mapped only if it is large and
+/// decoded in place.
+pub fn read_input_file(ctx: &KvContext, mut file: File, extension: &str) -> Result<FileData> {
+    let mut map = false;
+    if file.metadata()?.len() > SNIFF_SIZE as u64 {
+        let mut header = vec![0; SNIFF_SIZE];
+        file.read_exact(&mut header)?;
+        map = is_decoded_in_place(ctx, &header, extension);
+    }
+    read_file(file, map)
+}
+
+/// Checks if a large input is plain text by its first bytes, so it can be streamed to the
+/// printer. The last character of the header may be cut off.
+pub fn is_streamable_text(ctx: &KvContext, header: &[u8], extension: &str) -> bool {
+    if ctx.input_type == InputType::Text {
+        return true;
+    }
+    if ctx.input_type != InputType::Auto {
+        return false;
+    }
+    let handler = select_handler(ctx, configured_plugins(&ctx.plugins), header, extension);
+    if !matches!(
+        handler,
+        Handler::Builtin {
+            input_type: InputType::Auto,
+            ..
+        }
+    ) {
+        return false;
+    }
+    let is_utf8 = match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    is_utf8 && image::guess_format(header).is_err()
+}
+
+/// A plugin with its name.
+type PluginEntry = (String, Plugin);
+
+/// Returns the configured plugins in the order they are tried, loaded once per process.
+pub fn configured_plugins(options: &PluginOptions) -> &'static [PluginEntry] {
+    PLUGINS.get_or_init(|| sort_plugins(load_plugins(options)))
+}
+
+/// Splits the plugins matching an input into overriding and fallback plugins.
+fn matching_plugins<'a>(
+    ctx: &KvContext,
+    plugins: &'a [PluginEntry],
+    data: &[u8],
+    sniffed: &Sniffed,
+    extension: &str,
+) -> (Vec<&'a PluginEntry>, Vec<&'a PluginEntry>) {
+    plugins
+        .iter()
+        .filter(|(_, plugin)| {
+            plugin.matches(data, sniffed.mime, extension, ctx.source.as_deref())
+        })
+        .partition(|(_, plugin)| plugin.mode == PluginMode::Override)
+}
+
+/// The handler `load_data` uses for an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Handler {
+    /// A plugin running instead of the built-in handlers.
+    Plugin(String),
+    /// A built-in handler and the plugins tried if it fails.
+    Builtin {
+        input_type: InputType,
+        fallbacks: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for Handler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Handler::Plugin(name) => write!(f, "plugin {}", name),
+            Handler::Builtin {
+                input_type,
+                fallbacks,
+            } => {
+                match input_type {
+                    InputType::Auto => write!(
+                        f,
+                        "built-in image (text or hex dump if it cannot be decoded)"
+                    )?,
+                    _ => write!(f, "built-in {}", format!("{:?}", input_type).to_lowercase())?,
+                }
+                if !fallbacks.is_empty() {
+                    write!(f, ", fallback plugins: {}", fallbacks.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Chooses the handler for data like `load_data`, without loading it.
+pub fn select_handler(
+    ctx: &KvContext,
+    plugins: &[PluginEntry],
+    data: &[u8],
+    extension: &str,
+) -> Handler {
+    if ctx.input_type == InputType::Text || ctx.input_type == InputType::Hex {
+        return Handler::Builtin {
+            input_type: ctx.input_type,
+            fallbacks: Vec::new(),
+        };
+    }
+
+    let sniffed = Sniffed::new(data);
+    let (overrides, fallbacks) = matching_plugins(ctx, plugins, data, &sniffed, extension);
+    if let Some((name, _)) = overrides.first() {
+        return Handler::Plugin(name.clone());
+    }
+    Handler::Builtin {
+        input_type: builtin_handler(ctx, data, &sniffed, extension),
+        fallbacks: fallbacks.iter().map(|(name, _)| name.clone()).collect(),
+    }
+}
+
+/// Chooses the handler for a file like `load_file`, without loading it.
+pub fn select_file_handler(
+    ctx: &KvContext,
+    plugins: &[PluginEntry],
+    path: &Path,
+) -> Result<Handler> {
+    let ctx = &KvContext {
+        source: Some(path.to_path_buf()),
+        ..ctx.clone()
+    };
+    let extension = file_extension(path);
+
+    if is_html(ctx, &extension, path.to_string_lossy().as_bytes()) {
+        return Ok(Handler::Builtin {
+            input_type: InputType::Html,
+            fallbacks: Vec::new(),
+        });
+    }
+
+    // handlers are chosen by the first bytes, like for large files in load_file
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let mut header = Vec::new();
+    file.take(SNIFF_SIZE as u64).read_to_end(&mut header)?;
+    Ok(select_handler(ctx, plugins, &header, &extension))
 }
 
 pub fn load_data(ctx: &KvContext, data: &[u8], extension: &str) -> Result<LoadResult> {
     if ctx.input_type == InputType::Text {
         return Ok(LoadResult::Data(data.to_vec()));
     }
+    if ctx.input_type == InputType::Hex {
+        return Ok(load_hex(ctx, data));
+    }
 
-    let plugins = PLUGINS.get_or_init(load_plugins);
+    let sniffed = Sniffed::new(data);
+    let (overrides, fallbacks) = matching_plugins(
+        ctx,
+        configured_plugins(&ctx.plugins),
+        data,
+        &sniffed,
+        extension,
+    );
+
+    if let Some((_, plugin)) = overrides.first() {
+        return load_plugin(ctx, data, extension, plugin);
+    }
+
+    let err = match load_builtin(ctx, data, &sniffed, extension) {
+        Ok(result) => return Ok(result),
+        Err(err) => err,
+    };
 
-    for plugin in plugins.values() {
-        if has_extension_or_magic_bytes(
-            data,
-            extension,
-            plugin.magic_bytes.as_ref().unwrap_or(&vec![]),
-            &plugin.extensions,
-        ) {
-            return Ok(LoadResult::Image(render_plugin(ctx, data, plugin)?));
+    for (name, plugin) in fallbacks {
+        match load_plugin(ctx, data, extension, plugin) {
+            Ok(result) => return Ok(result),
+            Err(e) => eprintln!("Warning: Fallback plugin {} failed: {}", name, e),
+        }
+    }
+
+    // binary data is shown as hex dump instead of being refused, only inputs that were headed
+    // to a decoder of kv (a built-in type or a known image format) keep their error
+    if builtin_handler(ctx, data, &sniffed, extension) == InputType::Auto
+        && std::str::from_utf8(data).is_err()
+        && image::guess_format(data).is_err()
+    {
+        return Ok(load_hex(ctx, data));
+    }
+    Err(err)
+}
+
+/// Runs a plugin and loads its output with the built-in handlers for the declared output type.
+pub fn load_plugin(
+    ctx: &KvContext,
+    data: &[u8],
+    extension: &str,
+    plugin: &Plugin,
+) -> Result<LoadResult> {
+    let output_ctx = KvContext {
+        input_type: plugin.output,
+        ..ctx.clone()
+    };
+
+    match plugin.output {
+        InputType::Text => Ok(LoadResult::Text(
+            run_plugin(ctx, data, extension, plugin)?.concat(),
+        )),
+        InputType::Hex => Ok(load_hex(
+            ctx,
+            &run_plugin(ctx, data, extension, plugin)?.concat(),
+        )),
+        // text or image, only known after loading
+        InputType::Auto => {
+            let outputs = run_plugin(ctx, data, extension, plugin)?;
+            let [output] = outputs.as_slice() else {
+                return Ok(LoadResult::Image(render_image_pages(ctx, &outputs)?));
+            };
+            // the output is content, a line naming a file is text and not a file to load
+            let sniffed = Sniffed::new(output);
+            let result = match builtin_handler(&output_ctx, output, &sniffed, "") {
+                InputType::Auto => load_image_or_text(&output_ctx, output)?,
+                _ => load_builtin(&output_ctx, output, &sniffed, "")?,
+            };
+            match result {
+                LoadResult::Data(text) => Ok(LoadResult::Text(text)),
+                result => Ok(result),
+            }
+        }
+        _ => {
+            // the extension and file name may be passed to the plugin as placeholders
+            let filename = ctx.source.as_ref().and_then(|path| path.file_name());
+            let kind = format!("plugin {:?} {} {:?}", plugin, extension, filename);
+            let img = cached_render(ctx, &kind, &[data], || {
+                let outputs = run_plugin(ctx, data, extension, plugin)?;
+                if plugin.output == InputType::Image {
+                    return render_image_pages(ctx, &outputs);
+                }
+                let [output] = outputs.as_slice() else {
+                    anyhow::bail!("Multiple plugin outputs are only supported for images");
+                };
+                match load_builtin(&output_ctx, output, &Sniffed::new(output), "")? {
+                    LoadResult::Image(img) => Ok(img),
+                    _ => anyhow::bail!("Failed to render plugin output as {:?}", plugin.output),
+                }
+            })?;
+            Ok(LoadResult::Image(img))
         }
     }
+}
 
-    if ctx.input_type == InputType::Image {
-        return Ok(LoadResult::Image(render_image(ctx, data)?));
+/// Chooses the built-in handler for an input in the order `load_data` checks them.
+/// `Auto` decodes an image and falls back to text.
+pub fn builtin_handler(
+    ctx: &KvContext,
+    data: &[u8],
+    sniffed: &Sniffed,
+    extension: &str,
+) -> InputType {
+    if ctx.input_type == InputType::Text
+        || ctx.input_type == InputType::Image
+        || ctx.input_type == InputType::Hex
+    {
+        return ctx.input_type;
     }
 
     if ctx.input_type == InputType::Svg
@@ -281,44 +833,164 @@ pub fn load_data(ctx: &KvContext, data: &[u8], extension: &str) -> Result<LoadRe
         || data.starts_with(b"<svg")
         || data.starts_with(b"<?xml")
     {
-        return Ok(LoadResult::Image(render_svg(ctx, data)?));
+        return InputType::Svg;
     }
 
     if ctx.input_type == InputType::Pdf || extension == "pdf" || data.starts_with(b"%PDF") {
-        return Ok(LoadResult::Image(render_pdf(ctx, data)?));
+        return InputType::Pdf;
     }
+
+    // office documents without (known) extension are detected, e.g. from stdin
     if ctx.input_type == InputType::Office
-        || ["doc", "docx", "xls", "xlsx", "ppt", "pptx"].contains(&extension)
+        || OFFICE_EXTENSIONS.contains(&extension)
+        || (ctx.input_type == InputType::Auto && sniffed.office.is_some())
     {
-        return Ok(LoadResult::Image(render_office(ctx, data, extension)?));
+        return InputType::Office;
+    }
+
+    if is_markdown(ctx, extension) {
+        return InputType::Markdown;
     }
 
     if is_html(ctx, extension, data)
         || data.starts_with(b"<html")
         || data.starts_with(b"<!DOCTYPE html")
     {
-        return Ok(LoadResult::Image(render_html_chrome(ctx, data)?));
+        return InputType::Html;
     }
 
-    // fallback for InputType::Auto
-    match render_image(ctx, data) {
-        Ok(img) => Ok(LoadResult::Image(img)),
-        Err(err) => {
-            // check if it's a valid UTF-8 string that points to a file path
-            if let Ok(text) = std::str::from_utf8(data) {
-                let path_str = text.trim();
-                // Posix paths might contain \n, but this is so rare, we can ignore it for now
-                // check to avoid treating random text as paths
-                if !path_str.contains('\n') && !path_str.is_empty() {
-                    let path = PathBuf::from(path_str);
-                    if path.exists() && path.is_file() {
-                        return load_file(ctx, &path);
-                    }
+    InputType::Auto
+}
+
+/// Returns the extension LibreOffice imports an office document with.
+fn office_extension<'a>(data: &[u8], sniffed: &Sniffed, extension: &'a str) -> &'a str {
+    if OFFICE_EXTENSIONS.contains(&extension) {
+        extension
+    } else if let Some(detected) = sniffed.office {
+        detected
+    } else if !extension.is_empty() {
+        extension
+    } else if std::str::from_utf8(data).is_ok() {
+        // e.g. piped CSV, imported as spreadsheet
+        "csv"
+    } else {
+        "bin"
+    }
+}
+
+/// Loads data with the built-in handlers.
+fn load_builtin(
+    ctx: &KvContext,
+    data: &[u8],
+    sniffed: &Sniffed,
+    extension: &str,
+) -> Result<LoadResult> {
+    let img = match builtin_handler(ctx, data, sniffed, extension) {
+        InputType::Text => return Ok(LoadResult::Data(data.to_vec())),
+        InputType::Hex => return Ok(load_hex(ctx, data)),
+        InputType::Image => render_image(ctx, data)?,
+        InputType::Svg => render_svg(ctx, data)?,
+        InputType::Pdf => render_pdf(ctx, data)?,
+        InputType::Office => {
+            render_office(ctx, data, office_extension(data, sniffed, extension))?
+        }
+        // detected markdown is still readable as text without a browser
+        InputType::Markdown if ctx.input_type != InputType::Markdown => {
+            match render_markdown(ctx, data) {
+                Ok(img) => img,
+                Err(e) => {
+                    eprintln!("Warning: {:#}, showing markdown as text", e);
+                    return Ok(LoadResult::Data(data.to_vec()));
                 }
-                // determine it is just text data
-                return Ok(LoadResult::Data(data.to_vec()));
             }
-            Err(anyhow::anyhow!("Failed to decode input: {}", err))
+        }
+        InputType::Markdown => render_markdown(ctx, data)?,
+        InputType::Html => render_html_chrome(ctx, data)?,
+        InputType::Auto => return load_unknown(ctx, data),
+    };
+    Ok(LoadResult::Image(img))
+}
+
+/// Dumps the selected byte range of binary data as hex, the first `DUMP_SIZE` bytes if no range
+/// is selected.
+fn load_hex(ctx: &KvContext, data: &[u8]) -> LoadResult {
+    let (start, end) = ctx.byte_range.unwrap_or((0, DUMP_SIZE));
+    let start = start.min(data.len());
+    let end = end.clamp(start, data.len());
+    let mut dump = hex_dump(&data[start..end], start, ctx.hex_color);
+    if ctx.byte_range.is_none() && end < data.len() {
+        dump.push_str(&format!(
+            "... {} more bytes, select them with --bytes\n",
+            data.len() - end
+        ));
+    }
+    LoadResult::Dump(dump.into_bytes())
+}
+
+/// Loads data of unknown type as image, a path to a file, or text.
+fn load_unknown(ctx: &KvContext, data: &[u8]) -> Result<LoadResult> {
+    let result = load_image_or_text(ctx, data)?;
+    // check if it's a valid UTF-8 string that points to a file path
+    if let LoadResult::Data(text) = &result {
+        let path_str = String::from_utf8_lossy(text);
+        let path_str = path_str.trim();
+        // Posix paths might contain \n, but this is so rare, we can ignore it for now
+        // check to avoid treating random text as paths
+        if !path_str.contains('\n') && !path_str.is_empty() {
+            let path = PathBuf::from(path_str);
+            if path.exists() && path.is_file() {
+                return load_file(ctx, &path);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Loads data of unknown type as image or text.
+fn load_image_or_text(ctx: &KvContext, data: &[u8]) -> Result<LoadResult> {
+    match render_image(ctx, data) {
+        Ok(img) => Ok(LoadResult::Image(img)),
+        // determine it is just text data
+        Err(_) if std::str::from_utf8(data).is_ok() => Ok(LoadResult::Data(data.to_vec())),
+        Err(err) => Err(anyhow::anyhow!("Failed to decode input: {}", err)),
+    }
+}
+
+/// Loads a file as PDF document for PDF output.
+pub fn load_pdf_file(ctx: &KvContext, path: &Path) -> Result<Vec<u8>> {
+    // markdown references images relative to its file
+    let ctx = &KvContext {
+        source: Some(path.to_path_buf()),
+        ..ctx.clone()
+    };
+    let extension = file_extension(path);
+
+    let path_lossy = path.to_string_lossy();
+    if is_html(ctx, &extension, path_lossy.as_bytes()) {
+        return print_html_pdf(ctx, path_lossy.as_bytes(), ctx.page_indices.as_deref());
+    }
+
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    load_pdf_data(ctx, &data, &extension)
+}
+
+/// Loads data as PDF document for PDF output with the selected pages. HTML and markdown are
+/// printed, office documents converted and PDF documents copied.
+pub fn load_pdf_data(ctx: &KvContext, data: &[u8], extension: &str) -> Result<Vec<u8>> {
+    let pages = ctx.page_indices.as_deref();
+    let sniffed = Sniffed::new(data);
+    match builtin_handler(ctx, data, &sniffed, extension) {
+        InputType::Html => print_html_pdf(ctx, data, pages),
+        InputType::Markdown => print_markdown_pdf(ctx, data, pages),
+        InputType::Pdf => select_pdf_pages(ctx, data, pages),
+        InputType::Office => {
+            let extension = office_extension(data, &sniffed, extension);
+            let (pdf_data, pages) = convert_office(ctx, data, extension)?;
+            select_pdf_pages(ctx, &pdf_data, pages.as_deref())
+        }
+        _ => {
+            anyhow::bail!("PDF output is only supported for HTML, markdown, PDF and office inputs")
         }
     }
 }
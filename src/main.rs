@@ -1,9 +1,12 @@
-use crate::{pretty_print, send_image};
+use crate::{highlight_text, pretty_print, send_image};
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use kv::*;
-use std::io::{self, BufWriter, Read, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Read, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 use tempfile::NamedTempFile;
 
 #[cfg(test)]
@@ -35,6 +38,8 @@ enum InputTypeOption {
     Pdf,
     Html,
     Office,
+    Markdown,
+    Hex,
 }
 
 impl From<InputTypeOption> for InputType {
@@ -47,17 +52,137 @@ impl From<InputTypeOption> for InputType {
             InputTypeOption::Pdf => InputType::Pdf,
             InputTypeOption::Html => InputType::Html,
             InputTypeOption::Office => InputType::Office,
+            InputTypeOption::Markdown => InputType::Markdown,
+            InputTypeOption::Hex => InputType::Hex,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum MarkdownThemeOption {
+    Light,
+    Dark,
+}
+
+impl From<MarkdownThemeOption> for MarkdownTheme {
+    fn from(arg: MarkdownThemeOption) -> Self {
+        match arg {
+            MarkdownThemeOption::Light => MarkdownTheme::Light,
+            MarkdownThemeOption::Dark => MarkdownTheme::Dark,
+        }
+    }
+}
+
+/// Resize mode of the settings, the resize flags set it on the command line.
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum ResizeOption {
+    Clip,
+    Fit,
+    Width,
+    Height,
+    Original,
+}
+
+/// Clockwise rotation of images in degrees.
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum RotateOption {
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Right,
+    #[value(name = "180")]
+    Half,
+    #[value(name = "270")]
+    Left,
+}
+
+impl From<RotateOption> for u16 {
+    fn from(arg: RotateOption) -> Self {
+        match arg {
+            RotateOption::None => 0,
+            RotateOption::Right => 90,
+            RotateOption::Half => 180,
+            RotateOption::Left => 270,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum FlipOption {
+    Horizontal,
+    Vertical,
+}
+
+impl From<FlipOption> for Flip {
+    fn from(arg: FlipOption) -> Self {
+        match arg {
+            FlipOption::Horizontal => Flip::Horizontal,
+            FlipOption::Vertical => Flip::Vertical,
         }
     }
 }
 
 type TempAndFinalOption = Option<(NamedTempFile, PathBuf)>;
 
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+enum Command {
+    /// Manage the conversion cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Inspect and check the configured plugins
+    Plugins {
+        #[command(subcommand)]
+        action: PluginsAction,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+enum PluginsAction {
+    /// List plugins in the order they are tried, with their conditions and commands
+    List,
+    /// Validate the configuration and run each plugin on the first sample file it matches
+    Check {
+        /// Sample files to test the plugins with
+        #[arg(name = "SAMPLES")]
+        samples: Vec<PathBuf>,
+    },
+    /// Show which handler loads a file
+    Which {
+        /// File to check
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq)]
+enum CacheAction {
+    /// List cached files, least recently used first
+    List,
+    /// Print the total size of the cache
+    Size,
+    /// Remove all cached files
+    Clear,
+    /// Remove unused cached files and leftover source copies
+    Prune {
+        /// Remove files unused for longer than this (e.g. "7d", "12h")
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<Duration>,
+
+        /// Remove least recently used files until the cache fits (e.g. "500M", "2G")
+        #[arg(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+    },
+}
+
 /// A image viewer for the Kitty Terminal Graphics Protocol.
 #[derive(Parser, Debug, Clone)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Config {
-    /// Input files
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input files, after `--` if the first is named like a subcommand (e.g. `kv -- cache`)
     #[arg(name = "FILES")]
     files: Vec<PathBuf>,
 
@@ -109,19 +234,36 @@ struct Config {
     )]
     noresize: bool,
 
+    /// Ignore the orientation stored in images (EXIF, HEIF) instead of turning them upright
+    #[arg(long)]
+    no_orient: bool,
+
+    /// Rotate the rendered image (also documents and web pages) clockwise by this many degrees
+    #[arg(long, value_enum, value_name = "DEGREES", default_value_t = RotateOption::None)]
+    rotate: RotateOption,
+
+    /// Mirror the rendered image (also documents and web pages)
+    #[arg(long, value_enum)]
+    flip: Option<FlipOption>,
+
     /// Add background (useful for transparent images)
-    #[arg(short = 'b', long)]
+    #[arg(short = 'b', long, overrides_with = "no_background")]
     background: bool,
 
+    /// Do not add a background, even if the configuration enables it
+    #[arg(long, overrides_with = "background")]
+    no_background: bool,
+
     /// Set background color as hex string
-    #[arg(short = 'c', long, default_value = "#FFFFFF", requires = "background")]
+    #[arg(short = 'c', long, default_value = "#FFFFFF")]
     color: String,
 
     /// Set transmission mode
     #[arg(short = 'm', long, value_enum, default_value_t = ModeOption::Png)]
     mode: ModeOption,
 
-    /// Output to file as png, instead of kitty
+    /// Output to file as png, or as pdf of the selected pages for HTML, markdown, PDF and office
+    /// inputs, instead of kitty
     #[arg(short = 'o', long, conflicts_with = "mode")]
     output: Option<String>,
 
@@ -141,6 +283,10 @@ struct Config {
     #[arg(short = 'A', long, conflicts_with = "pages")]
     all: bool,
 
+    /// Set color scheme for rendered markdown
+    #[arg(long, value_enum, default_value_t = MarkdownThemeOption::Light)]
+    markdown_theme: MarkdownThemeOption,
+
     /// Set language for syntax highlighting (e.g. "toml")
     #[arg(short = 'l', long)]
     language: Option<String>,
@@ -149,14 +295,128 @@ struct Config {
     #[arg(short = 'N', long)]
     no_newline: bool,
 
-    /// Do not cache office files
+    /// Set the theme for syntax highlighting (see --list-themes)
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// Show line numbers of text
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Show a grid between line numbers, header and text
+    #[arg(long)]
+    grid: bool,
+
+    /// Show a header with the file name of text
+    #[arg(long)]
+    header: bool,
+
+    /// Print only these lines of text (e.g. "10:40", ":40", "10:", "10:+5"), can be repeated
+    #[arg(long, value_name = "N:M", value_parser = parse_line_range)]
+    lines: Vec<(usize, usize)>,
+
+    /// Highlight these lines of text (e.g. "12", "10:20"), can be repeated
+    #[arg(long, value_name = "N:M", value_parser = parse_line_range)]
+    highlight_line: Vec<(usize, usize)>,
+
+    /// Set the tab width of text (0 keeps tabs)
+    #[arg(long, value_name = "WIDTH")]
+    tab_width: Option<usize>,
+
+    /// Wrap long lines of text at the terminal width
+    #[arg(long)]
+    wrap: bool,
+
+    /// Render text as image, which is also done when writing text to a file with --output
+    #[arg(long)]
+    render_text: bool,
+
+    /// Set the monospaced font family of text rendered as image (default: a system monospace font)
+    #[arg(long, value_name = "FAMILY")]
+    font: Option<String>,
+
+    /// Show only this byte range in hex dumps (e.g. "0x100:0x200", "512:+64", ":1024")
+    #[arg(long, value_name = "START:END", value_parser = parse_byte_range)]
+    bytes: Option<(usize, usize)>,
+
+    /// List the themes for syntax highlighting
+    #[arg(long, conflicts_with = "list_languages")]
+    list_themes: bool,
+
+    /// List the languages for syntax highlighting with their file extensions
+    #[arg(long)]
+    list_languages: bool,
+
+    /// Print format, dimensions, color, frames, ICC profile, EXIF data and size instead of rendering
+    #[arg(long)]
+    info: bool,
+
+    /// Print the --info output as JSON
+    #[arg(long, requires = "info")]
+    json: bool,
+
+    /// Select a spreadsheet sheet by name or 1-indexed number, a name like "2024" is matched
+    /// first (pages select sheets otherwise), hidden and empty sheets are not counted
+    #[arg(long, value_name = "NAME|INDEX", conflicts_with_all = ["all", "pages"])]
+    sheet: Option<String>,
+
+    /// Render the notes pages of presentations (pages select slides), other documents are
+    /// rejected
+    #[arg(long)]
+    notes: bool,
+
+    /// Path to the soffice executable used for office documents
+    #[arg(long, value_name = "PATH")]
+    soffice: Option<PathBuf>,
+
+    /// Abort office conversions after this many seconds (0 disables the timeout)
+    #[arg(long, value_name = "SECONDS", default_value_t = 120)]
+    office_timeout: u64,
+
+    /// Do not cache conversions and renders
     #[arg(short = 'C', long)]
     no_cache: bool,
 
+    /// Evict least recently used cache entries beyond this size (e.g. "500M", "2G")
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "1G")]
+    cache_max_size: u64,
+
+    /// Directory for cached conversions and renders
+    #[arg(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+
+    /// Path to the pdfium library or the directory containing it
+    #[arg(long, value_name = "PATH")]
+    pdfium: Option<PathBuf>,
+
     /// Print filename before each input
     #[arg(short = 'p', long)]
     printname: bool,
 
+    /// Path to the Chrome/Chromium executable used for HTML rendering
+    #[arg(long, value_name = "PATH")]
+    browser: Option<PathBuf>,
+
+    /// Connect to a running browser via its DevTools websocket URL instead of launching one
+    #[arg(long, value_name = "URL", conflicts_with = "browser")]
+    browser_ws: Option<String>,
+
+    /// Print HTML to PDF and render its pages (works with --pages and --all)
+    #[arg(long)]
+    print: bool,
+
+    /// Never download a Chromium binary for HTML rendering
+    #[arg(long)]
+    no_download: bool,
+
+    /// Block all non-local network requests while rendering HTML
+    #[arg(long)]
+    offline: bool,
+
+    /// Disable JavaScript while rendering HTML
+    #[arg(long)]
+    no_js: bool,
+
     /// Force tty (ignore stdin check)
     #[arg(short = 't', long)]
     tty: bool,
@@ -168,8 +428,17 @@ struct Config {
     /// Print the plugins configuration file path (will be created if it doesn't exist)
     #[arg(long, conflicts_with = "remove")]
     plugins: bool,
+
+    /// Apply this plugins configuration on top of all other configuration files
+    #[arg(long, value_name = "PATH")]
+    plugin_config: Option<PathBuf>,
+
+    /// Load .kv/plugins.toml from the current directory or its parents (runs its commands)
+    #[arg(long)]
+    project_plugins: bool,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run(
     mut writer: impl Write,
     mut err_writer: impl Write,
@@ -177,13 +446,38 @@ fn run(
     conf: Config,
     term_size: (u32, u32),
     is_input_available: bool,
+    is_output_terminal: bool,
     cache_dir: Option<PathBuf>,
 ) -> Result<i32> {
+    let cache_dir = cache_dir.or_else(|| conf.cache_dir.clone());
+
+    if let Some(Command::Cache { action }) = &conf.command {
+        let cache_mode = match cache_dir {
+            Some(cache_dir) => CacheMode::Custom(cache_dir),
+            None => CacheMode::Default,
+        };
+        return run_cache(writer, err_writer, action, &cache_mode);
+    }
+
     if conf.remove {
         write!(writer, "\x1b_Ga=d\x1b\\")?;
         return Ok(0);
     }
 
+    if conf.list_themes {
+        for theme in list_themes() {
+            writeln!(writer, "{}", theme)?;
+        }
+        return Ok(0);
+    }
+
+    if conf.list_languages {
+        for (name, extensions) in list_languages() {
+            writeln!(writer, "{}: {}", name, extensions.join(", "))?;
+        }
+        return Ok(0);
+    }
+
     // If -t is passed, we ignore stdin even if input is available
     let use_stdin = is_input_available && !conf.tty;
 
@@ -256,17 +550,107 @@ fn run(
         term_size,
         page_indices,
         cache_mode,
+        cache_max_size: Some(conf.cache_max_size),
         background_color,
+        html: HtmlOptions {
+            browser_path: conf.browser.clone(),
+            ws_url: conf.browser_ws.clone(),
+            allow_download: !conf.no_download,
+            offline: conf.offline,
+            // a running browser cannot be cut off the network, so scripts must not reach it
+            javascript: !(conf.no_js || conf.offline && conf.browser_ws.is_some()),
+            print: conf.print,
+        },
+        office: OfficeOptions {
+            soffice_path: conf.soffice.clone(),
+            timeout: (conf.office_timeout > 0).then(|| Duration::from_secs(conf.office_timeout)),
+            sheet: conf.sheet.clone(),
+            notes: conf.notes,
+        },
+        markdown_theme: conf.markdown_theme.clone().into(),
+        image: ImageOptions {
+            orient: !conf.no_orient,
+            rotate: conf.rotate.clone().into(),
+            flip: conf.flip.clone().map(Flip::from),
+        },
+        plugins: PluginOptions {
+            config: conf.plugin_config.clone(),
+            project: conf.project_plugins,
+        },
+        pdfium_path: conf.pdfium.clone(),
+        byte_range: conf.bytes,
+        // escape codes would end up in a redirected dump, a rendered one keeps its colors
+        hex_color: is_output_terminal || conf.render_text || conf.output.is_some(),
+        source: None,
     };
 
+    if let Some(Command::Plugins { action }) = &conf.command {
+        return run_plugins(writer, err_writer, action, &ctx);
+    }
+
+    if conf.info {
+        return run_info(writer, err_writer, reader, &conf, &ctx, use_stdin);
+    }
+
+    let text_as_image = conf.render_text || conf.output.is_some();
+    let text_options = TextOptions {
+        language: conf.language.clone(),
+        theme: conf.theme.clone(),
+        line_numbers: conf.line_numbers,
+        grid: conf.grid,
+        header: conf.header,
+        line_ranges: conf.lines.clone(),
+        highlight_lines: conf.highlight_line.clone(),
+        tab_width: conf.tab_width,
+        wrap: conf.wrap,
+        // an image has no terminal width to wrap at
+        term_width: if text_as_image {
+            None
+        } else {
+            get_term_columns()
+        },
+        newline: !conf.no_newline,
+    };
+
+    // HTML is printed instead of rasterized when writing a PDF file
+    let export_pdf = conf
+        .output
+        .as_deref()
+        .is_some_and(|output| output.to_lowercase().ends_with(".pdf"));
+
     if use_stdin {
         if conf.printname {
             writeln!(err_writer, "stdin")?;
         }
 
+        // large text is streamed to the printer, its type is decided by the first bytes
         let mut data = Vec::new();
+        reader
+            .by_ref()
+            .take(SNIFF_SIZE as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() > SNIFF_SIZE
+            && !export_pdf
+            && !text_as_image
+            && is_streamable_text(&ctx, &data, "")
+        {
+            let input = PrinterInput::Reader(Box::new(Cursor::new(data).chain(reader)));
+            pretty_print(&mut writer, input, &text_options)?;
+            return Ok(0);
+        }
         reader.read_to_end(&mut data)?;
 
+        if export_pdf {
+            match load_pdf_data(&ctx, &data, "") {
+                Ok(pdf_data) => writer.write_all(&pdf_data)?,
+                Err(e) => {
+                    writeln!(err_writer, "Error decoding stdin: {}", e)?;
+                    return Ok(1);
+                }
+            }
+            return Ok(0);
+        }
+
         match load_data(&ctx, &data, "") {
             Ok(LoadResult::Image(img)) => {
                 send_image(
@@ -276,13 +660,19 @@ fn run(
                     conf.mode.clone().into(),
                 )?;
             }
-            Ok(LoadResult::Data(data)) => {
-                pretty_print(
-                    &mut writer,
-                    PrinterInput::Data(data),
-                    conf.language.as_deref(),
-                    !conf.no_newline,
-                )?;
+            Ok(LoadResult::Data(data) | LoadResult::Text(data)) => {
+                let text = highlight_text(PrinterInput::Data(data), &text_options)?;
+                show_text(&mut writer, &conf, &ctx, &text)?;
+            }
+            Ok(LoadResult::TextFile(path)) if !text_as_image => {
+                pretty_print(&mut writer, PrinterInput::File(path), &text_options)?;
+            }
+            Ok(LoadResult::TextFile(path)) => {
+                let text = highlight_text(PrinterInput::File(path), &text_options)?;
+                show_text(&mut writer, &conf, &ctx, &text)?;
+            }
+            Ok(LoadResult::Dump(dump)) => {
+                show_text(&mut writer, &conf, &ctx, &String::from_utf8_lossy(&dump))?;
             }
             Err(e) => {
                 writeln!(err_writer, "Error decoding stdin: {}", e)?;
@@ -295,6 +685,17 @@ fn run(
             if conf.printname {
                 writeln!(err_writer, "{}", path.display())?;
             }
+            // a single file, PDFs cannot be concatenated (see the --output check above)
+            if export_pdf {
+                match load_pdf_file(&ctx, path) {
+                    Ok(pdf_data) => writer.write_all(&pdf_data)?,
+                    Err(e) => {
+                        writeln!(err_writer, "Error loading {}: {}", path.display(), e)?;
+                        exit_code = 1;
+                    }
+                }
+                continue;
+            }
             match load_file(&ctx, path) {
                 Ok(LoadResult::Image(img)) => {
                     send_image(
@@ -304,13 +705,19 @@ fn run(
                         conf.mode.clone().into(),
                     )?;
                 }
-                Ok(LoadResult::Data(_)) => {
-                    pretty_print(
-                        &mut writer,
-                        PrinterInput::File(path.clone()),
-                        conf.language.as_deref(),
-                        !conf.no_newline,
-                    )?;
+                Ok(LoadResult::Data(_) | LoadResult::TextFile(_)) if !text_as_image => {
+                    pretty_print(&mut writer, PrinterInput::File(path.clone()), &text_options)?;
+                }
+                Ok(LoadResult::Data(_) | LoadResult::TextFile(_)) => {
+                    let text = highlight_text(PrinterInput::File(path.clone()), &text_options)?;
+                    show_text(&mut writer, &conf, &ctx, &text)?;
+                }
+                Ok(LoadResult::Text(data)) => {
+                    let text = highlight_text(PrinterInput::Data(data), &text_options)?;
+                    show_text(&mut writer, &conf, &ctx, &text)?;
+                }
+                Ok(LoadResult::Dump(dump)) => {
+                    show_text(&mut writer, &conf, &ctx, &String::from_utf8_lossy(&dump))?;
                 }
                 Err(e) => {
                     writeln!(err_writer, "Error loading {}: {}", path.display(), e)?;
@@ -330,6 +737,359 @@ fn run(
     Ok(0)
 }
 
+/// Prints the properties of the inputs without rendering them, as a JSON array with --json.
+fn run_info(
+    mut writer: impl Write,
+    mut err_writer: impl Write,
+    mut reader: impl Read,
+    conf: &Config,
+    ctx: &KvContext,
+    use_stdin: bool,
+) -> Result<i32> {
+    let mut results = Vec::new();
+    if use_stdin {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        results.push(("stdin".to_string(), media_info(ctx, "stdin", &data)));
+    } else if !conf.files.is_empty() {
+        for path in &conf.files {
+            let name = path.display().to_string();
+            let info = File::open(path)
+                .with_context(|| format!("Failed to open {}", name))
+                .and_then(|file| read_input_file(ctx, file, &file_extension(path)))
+                .and_then(|data| media_info(ctx, &name, &data));
+            results.push((name, info));
+        }
+    } else {
+        writeln!(
+            err_writer,
+            "Error: No input files provided and no data piped to stdin."
+        )?;
+        return Ok(1);
+    }
+
+    let mut exit_code = 0;
+    let mut infos = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(info) => infos.push(info),
+            Err(e) => {
+                writeln!(err_writer, "Error loading {}: {:#}", name, e)?;
+                exit_code = 1;
+            }
+        }
+    }
+
+    if conf.json {
+        writeln!(writer, "{}", serde_json::to_string_pretty(&infos)?)?;
+    } else {
+        for info in &infos {
+            write!(writer, "{}", format_media_info(info))?;
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Prints text with ANSI colors, or renders it as image when writing a file or with --render-text.
+fn show_text(writer: &mut dyn Write, conf: &Config, ctx: &KvContext, text: &str) -> Result<()> {
+    if conf.output.is_none() && !conf.render_text {
+        writer.write_all(text.as_bytes())?;
+        writer.flush()?;
+        return Ok(());
+    }
+    // a file is not clipped to the terminal, only explicit sizes are applied
+    let mut ctx = ctx.clone();
+    if conf.output.is_some() && !matches!(ctx.resize_mode, ResizeMode::Manual { .. }) {
+        ctx.resize_mode = ResizeMode::Original;
+    }
+    let img = render_text(&ctx, text, conf.font.as_deref())?;
+    send_image(writer, img, conf.output.clone(), conf.mode.clone().into())
+}
+
+/// Applies the settings to all options that are not given on the command line. Invalid values
+/// are skipped with a warning, the option keeps its default.
+fn apply_settings(
+    conf: &mut Config,
+    matches: &ArgMatches,
+    settings: Settings,
+    err_writer: &mut dyn Write,
+) -> Result<()> {
+    let is_set = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    if let Some(background) = settings.background
+        && !is_set("background")
+        && !is_set("no_background")
+    {
+        conf.background = background;
+    }
+    if let Some(color) = settings.color
+        && !is_set("color")
+    {
+        match parse_color(&color) {
+            Ok(_) => conf.color = color,
+            Err(e) => writeln!(err_writer, "Warning: Invalid color in settings: {}", e)?,
+        }
+    }
+    if let Some(mode) = settings.mode
+        && !is_set("mode")
+    {
+        match ModeOption::from_str(&mode, true) {
+            Ok(mode) => conf.mode = mode,
+            Err(e) => writeln!(err_writer, "Warning: Invalid mode in settings: {}", e)?,
+        }
+    }
+    if let Some(markdown_theme) = settings.markdown_theme
+        && !is_set("markdown_theme")
+    {
+        match MarkdownThemeOption::from_str(&markdown_theme, true) {
+            Ok(markdown_theme) => conf.markdown_theme = markdown_theme,
+            Err(e) => writeln!(
+                err_writer,
+                "Warning: Invalid markdown theme in settings: {}",
+                e
+            )?,
+        }
+    }
+
+    // any resize flag replaces the resize mode of the settings
+    let resize_flags = [
+        "width",
+        "height",
+        "fullwidth",
+        "fullheight",
+        "resize",
+        "noresize",
+    ];
+    if let Some(resize) = settings.resize
+        && !resize_flags.iter().any(|id| is_set(id))
+    {
+        match ResizeOption::from_str(&resize, true) {
+            Ok(ResizeOption::Clip) => {}
+            Ok(ResizeOption::Fit) => conf.resize = true,
+            Ok(ResizeOption::Width) => conf.fullwidth = true,
+            Ok(ResizeOption::Height) => conf.fullheight = true,
+            Ok(ResizeOption::Original) => conf.noresize = true,
+            Err(e) => writeln!(
+                err_writer,
+                "Warning: Invalid resize mode in settings: {}",
+                e
+            )?,
+        }
+    }
+
+    if let Some(theme) = settings.theme
+        && conf.theme.is_none()
+    {
+        if list_themes().contains(&theme) {
+            conf.theme = Some(theme);
+        } else {
+            writeln!(err_writer, "Warning: Invalid theme in settings: {}", theme)?;
+        }
+    }
+    if let Some(font) = settings.font
+        && conf.font.is_none()
+    {
+        if is_font_installed(&font) {
+            conf.font = Some(font);
+        } else {
+            writeln!(
+                err_writer,
+                "Warning: Invalid font in settings: Font not found: {}",
+                font
+            )?;
+        }
+    }
+    conf.cache_dir = conf.cache_dir.take().or(settings.cache_dir);
+    if let Some(pdfium) = settings.pdfium
+        && conf.pdfium.is_none()
+    {
+        if pdfium.exists() {
+            conf.pdfium = Some(pdfium);
+        } else {
+            writeln!(
+                err_writer,
+                "Warning: Invalid pdfium in settings: Not found: {}",
+                pdfium.display()
+            )?;
+        }
+    }
+    // an explicit browser websocket replaces the browser of the settings
+    if let Some(browser) = settings.browser
+        && conf.browser.is_none()
+        && conf.browser_ws.is_none()
+    {
+        if browser.is_file() {
+            conf.browser = Some(browser);
+        } else {
+            writeln!(
+                err_writer,
+                "Warning: Invalid browser in settings: Not found: {}",
+                browser.display()
+            )?;
+        }
+    }
+
+    if let Some(project_plugins) = settings.project_plugins
+        && !is_set("project_plugins")
+    {
+        conf.project_plugins = project_plugins;
+    }
+
+    // checked after merging, the background may be enabled by the settings
+    if is_set("color") && !conf.background {
+        anyhow::bail!(
+            "--color requires a background, pass -b or set background = true in the settings"
+        );
+    }
+    Ok(())
+}
+
+fn run_plugins(
+    mut writer: impl Write,
+    mut err_writer: impl Write,
+    action: &PluginsAction,
+    ctx: &KvContext,
+) -> Result<i32> {
+    let config_paths = plugin_config_paths(&ctx.plugins);
+    let plugins = match read_plugin_configs(&config_paths) {
+        Ok(plugins) => sort_plugins(plugins),
+        Err(e) => {
+            writeln!(err_writer, "Error: {:#}", e)?;
+            return Ok(1);
+        }
+    };
+
+    match action {
+        PluginsAction::List => {
+            for path in &config_paths {
+                writeln!(writer, "config: {}", path.display())?;
+            }
+            for (name, plugin) in &plugins {
+                let program = plugin.program().unwrap_or_default();
+                let location = find_executable(&program)
+                    .map_or("not found".to_string(), |path| path.display().to_string());
+                writeln!(
+                    writer,
+                    "{} (priority {}, {}) -> {}",
+                    name,
+                    plugin.priority,
+                    format!("{:?}", plugin.mode).to_lowercase(),
+                    format!("{:?}", plugin.output).to_lowercase()
+                )?;
+                writeln!(writer, "  matches: {}", plugin.matchers().join("; "))?;
+                writeln!(writer, "  command: {} ({})", program, location)?;
+            }
+        }
+        PluginsAction::Check { samples } => {
+            let samples = samples
+                .iter()
+                .map(|path| {
+                    let data = std::fs::read(path)
+                        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+                    Ok((path, file_extension(path), sniff_mime(&data), data))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut exit_code = 0;
+            for (name, plugin) in &plugins {
+                let program = plugin.program().unwrap_or_default();
+                let sample = samples.iter().find(|(path, extension, mime, data)| {
+                    plugin.matches(data, mime, extension, Some(path.as_path()))
+                });
+
+                let result = plugin.validate().and_then(|_| {
+                    find_executable(&program)
+                        .with_context(|| format!("Program not found: {}", program))?;
+                    let Some((path, extension, _, data)) = sample else {
+                        return Ok("no matching sample".to_string());
+                    };
+                    // a dry run must not be answered from the cache
+                    let ctx = KvContext {
+                        source: Some(path.to_path_buf()),
+                        cache_mode: CacheMode::Disabled,
+                        ..ctx.clone()
+                    };
+                    load_plugin(&ctx, data, extension, plugin)?;
+                    Ok(format!("ran on {}", path.display()))
+                });
+
+                match result {
+                    Ok(message) => writeln!(writer, "ok    {} ({})", name, message)?,
+                    Err(e) => {
+                        writeln!(writer, "error {}: {:#}", name, e)?;
+                        exit_code = 1;
+                    }
+                }
+            }
+            return Ok(exit_code);
+        }
+        PluginsAction::Which { file } => match select_file_handler(ctx, &plugins, file) {
+            Ok(handler) => writeln!(writer, "{}: {}", file.display(), handler)?,
+            Err(e) => {
+                writeln!(err_writer, "Error: {:#}", e)?;
+                return Ok(1);
+            }
+        },
+    }
+
+    Ok(0)
+}
+
+fn run_cache(
+    mut writer: impl Write,
+    mut err_writer: impl Write,
+    action: &CacheAction,
+    cache_mode: &CacheMode,
+) -> Result<i32> {
+    let Some(dir) = cache_dir(cache_mode) else {
+        writeln!(err_writer, "Error: Caching is disabled")?;
+        return Ok(1);
+    };
+
+    match action {
+        CacheAction::List => {
+            for entry in cache_entries(&dir)? {
+                let age = entry.last_used.elapsed().unwrap_or_default().as_secs();
+                writeln!(
+                    writer,
+                    "{:>8}  {:>4}d {:>2}h  {}",
+                    format_size(entry.size),
+                    age / (24 * 60 * 60),
+                    age / (60 * 60) % 24,
+                    entry.path.display()
+                )?;
+            }
+        }
+        CacheAction::Size => {
+            writeln!(
+                writer,
+                "{}  {}",
+                format_size(cache_size(&dir)?),
+                dir.display()
+            )?;
+        }
+        CacheAction::Clear => {
+            let removed = clear_cache(&dir)?;
+            writeln!(err_writer, "Removed {} cached files", removed.len())?;
+        }
+        CacheAction::Prune {
+            older_than,
+            max_size,
+        } => {
+            let removed = prune_cache(&dir, *older_than, *max_size)?;
+            let size = removed.iter().map(|entry| entry.size).sum();
+            writeln!(
+                err_writer,
+                "Removed {} cached files ({})",
+                removed.len(),
+                format_size(size)
+            )?;
+        }
+    }
+
+    Ok(0)
+}
+
 fn prepare_writer(
     output: Option<String>,
     overwrite: bool,
@@ -376,7 +1136,9 @@ fn prepare_writer(
 }
 
 fn main() -> Result<()> {
-    let conf = Config::parse();
+    let matches = Config::command().get_matches();
+    let mut conf = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    apply_settings(&mut conf, &matches, load_settings(), &mut io::stderr())?;
 
     if conf.plugins {
         open_config()?;
@@ -387,24 +1149,30 @@ fn main() -> Result<()> {
 
     // Detect TTY status
     let is_input_available = atty::isnt(atty::Stream::Stdin);
+    let is_output_terminal = atty::is(atty::Stream::Stdout);
 
     let (writer, temp_output) = prepare_writer(conf.output.clone(), conf.overwrite)?;
 
-    let code = run(
-        writer,
-        io::stderr(),
-        io::stdin(),
-        conf,
-        term_size,
-        is_input_available,
-        None,
-    )?;
+    // process::exit below skips destructors, the browser has to be closed before
+    let code = {
+        let _browser = BrowserGuard;
+        run(
+            writer,
+            io::stderr(),
+            io::stdin(),
+            conf,
+            term_size,
+            is_input_available,
+            is_output_terminal,
+            None,
+        )?
+    };
 
     // Commit temp file only on success
-    if let Some((tempfile, final_path)) = temp_output {
-        if code == 0 {
-            tempfile.persist(final_path)?;
-        }
+    if let Some((tempfile, final_path)) = temp_output
+        && code == 0
+    {
+        tempfile.persist(final_path)?;
     }
 
     std::process::exit(code);
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::process::{Child, Command, Output};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+#[cfg(test)]
+mod tests_process;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Process group of the isolated child that is waited for, 0 if there is none.
+#[cfg(unix)]
+static CHILD_GROUP: AtomicI32 = AtomicI32::new(0);
+
+/// Reads a piped output stream to the end on a separate thread.
+fn drain<R: Read + Send + 'static>(stream: Option<R>) -> Option<JoinHandle<Vec<u8>>> {
+    stream.map(|mut stream| {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = stream.read_to_end(&mut buffer);
+            buffer
+        })
+    })
+}
+
+fn join(handle: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    handle
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default()
+}
+
+/// Starts the command in its own process group if it has a timeout, so that the timeout kills
+/// its whole process tree. Without a timeout it stays in the foreground process group and gets
+/// the Ctrl-C of the terminal like kv itself.
+pub fn isolate_process_group(cmd: &mut Command, timeout: Option<Duration>) {
+    #[cfg(unix)]
+    if timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = (cmd, timeout);
+}
+
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let group = CHILD_GROUP.load(Ordering::SeqCst);
+    // SAFETY: kill, signal and raise are async-signal-safe
+    unsafe {
+        if group > 0 {
+            libc::kill(-group, signal);
+        }
+        // terminate kv as the signal would have without the handler
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Forwards SIGINT and SIGTERM to the process group of a child while it is alive, an isolated
+/// group does not get them from the terminal. The previous handlers are restored on drop.
+struct SignalForward {
+    #[cfg(unix)]
+    previous: [libc::sighandler_t; 2],
+}
+
+#[cfg(unix)]
+const FORWARDED_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+impl SignalForward {
+    fn new(child: &Child) -> Self {
+        #[cfg(unix)]
+        {
+            CHILD_GROUP.store(child.id() as i32, Ordering::SeqCst);
+            let handler = forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only calls async-signal-safe functions
+            let previous = FORWARDED_SIGNALS.map(|signal| unsafe { libc::signal(signal, handler) });
+            SignalForward { previous }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = child;
+            SignalForward {}
+        }
+    }
+}
+
+impl Drop for SignalForward {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            for (signal, previous) in FORWARDED_SIGNALS.into_iter().zip(self.previous) {
+                // SAFETY: restores the handler that was installed before
+                unsafe {
+                    libc::signal(signal, previous);
+                }
+            }
+            CHILD_GROUP.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Kills a child process and its descendants.
+fn kill_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: kill has no memory safety requirements, a negative pid addresses the process group
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(windows)]
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &child.id().to_string()])
+        .output();
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Writes data to the piped stdin of a child on a separate thread and closes it afterwards.
+/// A child that does not read its input must not block the caller before the timeout applies.
+pub fn write_stdin(child: &mut Child, data: &[u8]) -> Result<()> {
+    let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+    let data = data.to_vec();
+    std::thread::spawn(move || {
+        // the child may exit without reading all input, its exit status tells if it failed
+        let _ = stdin.write_all(&data);
+    });
+    Ok(())
+}
+
+/// Waits for a child process and collects its piped stdout and stderr.
+/// The child and its process group are killed if it does not exit within `timeout`.
+pub fn wait_with_timeout(mut child: Child, timeout: Option<Duration>) -> Result<Output> {
+    // drain pipes while waiting, a full pipe would block the child forever
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    // children with a timeout run in their own process group
+    let _forward = timeout.map(|_| SignalForward::new(&child));
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for process")? {
+            break status;
+        }
+
+        if let Some(timeout) = timeout
+            && start.elapsed() >= timeout
+        {
+            kill_process_tree(&mut child);
+            anyhow::bail!("Process timed out after {}s", timeout.as_secs_f32());
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: join(stdout),
+        stderr: join(stderr),
+    })
+}
+
+/// Returns the trimmed stderr, or stdout if stderr is empty, for error messages.
+pub fn output_message(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        return stderr.trim().to_string();
+    }
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
@@ -0,0 +1,77 @@
+use super::*;
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_wait_with_timeout() {
+    let child = Command::new("sh")
+        .args(["-c", "echo out; echo err >&2; exit 3"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let output = wait_with_timeout(child, Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"out\n");
+    assert_eq!(output_message(&output), "err");
+}
+
+#[test]
+fn test_wait_with_timeout_expired() {
+    let child = Command::new("sleep").arg("10").spawn().unwrap();
+
+    let start = Instant::now();
+    let result = wait_with_timeout(child, Some(Duration::from_millis(100)));
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Process timed out after 0.1s"
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_wait_with_timeout_kills_process_tree() {
+    // the grandchild keeps stdout open, it has to be killed with the shell
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "sleep 10; echo done"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let timeout = Some(Duration::from_millis(100));
+    isolate_process_group(&mut cmd, timeout);
+    let child = cmd.spawn().unwrap();
+
+    let start = Instant::now();
+    assert!(wait_with_timeout(child, timeout).is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[rstest::rstest]
+#[case(None, false)]
+#[case(Some(Duration::from_secs(10)), true)]
+fn test_isolate_process_group(#[case] timeout: Option<Duration>, #[case] isolated: bool) {
+    let mut cmd = Command::new("sleep");
+    cmd.arg("10");
+    isolate_process_group(&mut cmd, timeout);
+    let mut child = cmd.spawn().unwrap();
+
+    // SAFETY: getpgid has no memory safety requirements
+    let group = unsafe { libc::getpgid(child.id() as libc::pid_t) };
+    let _ = child.kill();
+    let _ = child.wait();
+    assert_eq!(group == child.id() as libc::pid_t, isolated);
+}
+
+#[test]
+fn test_write_stdin() {
+    let mut child = Command::new("cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    write_stdin(&mut child, b"input").unwrap();
+    let output = wait_with_timeout(child, Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(output.stdout, b"input");
+}
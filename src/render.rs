@@ -1,26 +1,65 @@
 use anyhow::{Context, Result};
 use image::imageops::FilterType;
-use image::{DynamicImage, GenericImage, GenericImageView, ImageReader, Rgba, RgbaImage};
+use image::metadata::Orientation;
+use image::{
+    DynamicImage, GenericImage, GenericImageView, ImageDecoder, ImageReader, Rgba, RgbaImage,
+};
 use libheif_rs::integration::image::register_all_decoding_hooks;
-use std::io::{Cursor, Write};
+use libheif_rs::{ColorSpace, DecodingOptions, HeifContext, LibHeif, RgbChroma};
+use std::io::Cursor;
 use std::process::{Command, Stdio};
 
-use crate::{CacheMode, Plugin, ResizeMode, calculate_dimensions, kv_project_dirs};
+use crate::{Plugin, ResizeMode, calculate_dimensions, format_pages, kv_project_dirs};
 
 use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
 
-use crate::{InputType, KvContext};
+use crate::{
+    Flip, HtmlOptions, ImageOptions, InputType, KvContext, PRESENTATION_EXTENSIONS,
+    SPREADSHEET_EXTENSIONS, cache_dir, cached_render, evict_cache, isolate_process_group,
+    loaded_files_manifest, lock_cache_entry, output_message, read_loaded_files,
+    render_cache_key, resolve_sheet, sniff_mime, spreadsheet_sheet_names, touch_cache_entry,
+    wait_with_timeout, write_cache_file, write_loaded_files, write_stdin,
+};
 use base64::{Engine as _, engine::general_purpose};
-use std::path::PathBuf;
-
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+use headless_chrome::browser::default_executable;
+use headless_chrome::browser::tab::RequestPausedDecision;
+use headless_chrome::browser::transport::{SessionId, Transport};
+use headless_chrome::protocol::cdp::Emulation::SetScriptExecutionDisabled;
+use headless_chrome::protocol::cdp::Fetch::events::RequestPausedEvent;
+use headless_chrome::protocol::cdp::Fetch::{FailRequest, RequestPattern, RequestStage};
+use headless_chrome::protocol::cdp::Network::ErrorReason;
 use headless_chrome::protocol::cdp::Page::CaptureScreenshotFormatOption;
-use headless_chrome::{Browser, LaunchOptions};
+use headless_chrome::types::PrintToPdfOptions;
+use headless_chrome::{Browser, LaunchOptions, Tab};
 
 use sha2::{Digest, Sha256};
 
+mod markdown;
+pub use markdown::*;
+
+mod text;
+pub use text::*;
+
+mod color;
+pub use color::*;
+
 #[cfg(test)]
 mod tests_render;
 
+/// Browser shared by all HTML inputs of this process, with its launched profile directory.
+static BROWSER: Mutex<Option<(Browser, Option<TempDir>)>> = Mutex::new(None);
+
+/// Keep the DevTools connection open between inputs (e.g. while converting other files).
+const BROWSER_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
 pub fn add_background(img: &DynamicImage, color: &Rgba<u8>) -> DynamicImage {
     let mut bg = RgbaImage::from_pixel(img.width(), img.height(), *color);
     let rgba = img.to_rgba8();
@@ -48,12 +87,139 @@ pub fn add_background(img: &DynamicImage, color: &Rgba<u8>) -> DynamicImage {
 }
 
 pub fn render_image(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+    let img = decode_image(data, ctx.image.orient)?;
+    Ok(finish_image(ctx, img))
+}
+
+/// Decodes image data in any supported format without resizing it, converted to sRGB from an
+/// embedded color profile and turned upright by its orientation if `orient` is set.
+pub fn decode_image(data: &[u8], orient: bool) -> Result<DynamicImage> {
+    if matches!(sniff_mime(data), "image/heic" | "image/heif") {
+        return decode_heif(data, orient);
+    }
+
     register_all_decoding_hooks();
-    let mut img = ImageReader::new(Cursor::new(&data))
+    let mut decoder = ImageReader::new(Cursor::new(&data))
         .with_guessed_format()
         .context("Failed to guess image format")?
-        .decode()
+        .into_decoder()
         .context("Failed to decode image data")?;
+    // images with broken metadata are still shown, just not rotated or color managed
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let icc_profile = decoder.icc_profile().ok().flatten();
+
+    // the decoder converts CMYK without the profile and misses inverted values
+    let cmyk = cmyk_jpeg_header(data).and_then(|header| {
+        decode_cmyk_jpeg(data, &header, icc_profile.as_deref())
+            .inspect_err(|e| eprintln!("Warning: {:#}, decoding it without color management", e))
+            .ok()
+    });
+    let mut img = match cmyk {
+        Some(img) => img,
+        None => {
+            let img = DynamicImage::from_decoder(decoder).context("Failed to decode image data")?;
+            match &icc_profile {
+                Some(profile) => convert_to_srgb(img, profile),
+                None => img,
+            }
+        }
+    };
+    if orient {
+        img.apply_orientation(orientation);
+    }
+    Ok(img)
+}
+
+/// Decodes the primary image of a HEIF file with libheif, which applies the rotation,
+/// mirroring and cropping of the container unless `orient` is unset.
+fn decode_heif(data: &[u8], orient: bool) -> Result<DynamicImage> {
+    let context = HeifContext::read_from_bytes(data).context("Failed to read HEIF data")?;
+    let handle = context
+        .primary_image_handle()
+        .context("Failed to read HEIF primary image")?;
+
+    let mut options = DecodingOptions::new().context("Failed to allocate HEIF options")?;
+    options.set_ignore_transformations(!orient);
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), Some(options))
+        .context("Failed to decode HEIF image")?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .context("Failed to decode HEIF image: no interleaved plane")?;
+    let row_size = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_size * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_size]);
+    }
+    let buffer = RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .context("Failed to decode HEIF image: invalid plane size")?;
+    let img = DynamicImage::ImageRgba8(buffer);
+    Ok(match handle.color_profile_raw() {
+        Some(profile) => convert_to_srgb(img, &profile.data),
+        None => img,
+    })
+}
+
+/// Rotates and mirrors a rendered image as requested by the user.
+pub fn transform_image(img: DynamicImage, options: &ImageOptions) -> DynamicImage {
+    let img = match options.rotate {
+        90 => img.rotate90(),
+        180 => img.rotate180(),
+        270 => img.rotate270(),
+        _ => img,
+    };
+    match options.flip {
+        Some(Flip::Horizontal) => img.fliph(),
+        Some(Flip::Vertical) => img.flipv(),
+        None => img,
+    }
+}
+
+/// Renders images as pages, selected by the page indices and stacked vertically.
+pub fn render_image_pages(ctx: &KvContext, pages: &[Vec<u8>]) -> Result<DynamicImage> {
+    let selected_indices: Vec<usize> = match &ctx.page_indices {
+        Some(page_indices) => {
+            if page_indices.iter().any(|&i| i as usize >= pages.len()) {
+                anyhow::bail!("Page index out of range (must be <= {})", pages.len());
+            }
+            page_indices.iter().map(|&i| i as usize).collect()
+        }
+        None => (0..pages.len()).collect(),
+    };
+
+    if let [index] = selected_indices.as_slice() {
+        return render_image(ctx, &pages[*index]);
+    }
+
+    let images = selected_indices
+        .into_iter()
+        .map(|i| Ok(decode_image(&pages[i], ctx.image.orient)?.to_rgba8()))
+        .collect::<Result<Vec<_>>>()?;
+    let combined = DynamicImage::ImageRgba8(stack_images(images)?);
+    Ok(finish_image(ctx, combined))
+}
+
+/// Stacks images vertically, left-aligned.
+fn stack_images(images: Vec<RgbaImage>) -> Result<RgbaImage> {
+    let max_width = images.iter().map(|img| img.width()).max().unwrap_or(0);
+    let total_height = images.iter().map(|img| img.height()).sum::<u32>();
+
+    let mut combined = RgbaImage::new(max_width, total_height);
+    let mut current_y = 0;
+    for img in images {
+        combined.copy_from(&img, 0, current_y)?;
+        current_y += img.height();
+    }
+    Ok(combined)
+}
+
+/// Rotates and mirrors a decoded image, then resizes it and applies the background color.
+/// Every image result passes through here once, except rasterized SVG which is transformed
+/// on its own.
+pub fn finish_image(ctx: &KvContext, img: DynamicImage) -> DynamicImage {
+    let mut img = transform_image(img, &ctx.image);
     let (w, h) = calculate_dimensions(img.dimensions(), ctx.resize_mode, ctx.term_size);
 
     if w != 0 && h != 0 && (w != img.width() || h != img.height()) {
@@ -63,13 +229,24 @@ pub fn render_image(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
     if let Some(color) = ctx.background_color {
         img = add_background(&img, &color);
     }
-    Ok(img)
+    img
 }
 
 pub fn render_svg(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+    cached_render(ctx, "svg", &[data], || rasterize_svg(ctx, data))
+}
+
+fn rasterize_svg(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
     let mut fontdb = usvg::fontdb::Database::new();
     fontdb.load_system_fonts();
+    rasterize_svg_with_fonts(ctx, data, fontdb)
+}
 
+fn rasterize_svg_with_fonts(
+    ctx: &KvContext,
+    data: &[u8],
+    fontdb: usvg::fontdb::Database,
+) -> Result<DynamicImage> {
     let opt = usvg::Options {
         fontdb: std::sync::Arc::new(fontdb),
         ..Default::default()
@@ -78,11 +255,22 @@ pub fn render_svg(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
     let tree = usvg::Tree::from_data(data, &opt).context("Failed to parse SVG")?;
     let size = tree.size().to_int_size();
 
-    let (new_w, new_h) = calculate_dimensions(
-        (size.width(), size.height()),
-        ctx.resize_mode,
-        ctx.term_size,
-    );
+    // the rotated image is fit into the requested size
+    let quarter_turn = ctx.image.rotate % 180 == 90;
+    let (new_w, new_h) = if quarter_turn {
+        let (h, w) = calculate_dimensions(
+            (size.height(), size.width()),
+            ctx.resize_mode,
+            ctx.term_size,
+        );
+        (w, h)
+    } else {
+        calculate_dimensions(
+            (size.width(), size.height()),
+            ctx.resize_mode,
+            ctx.term_size,
+        )
+    };
 
     let mut pixmap = tiny_skia::Pixmap::new(new_w, new_h)
         .ok_or_else(|| anyhow::anyhow!("Failed to create pixmap"))?;
@@ -102,23 +290,65 @@ pub fn render_svg(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
     let buffer = RgbaImage::from_raw(new_w, new_h, pixmap.data().to_vec())
         .ok_or_else(|| anyhow::anyhow!("Failed buffer conversion"))?;
 
-    Ok(DynamicImage::ImageRgba8(buffer))
+    Ok(transform_image(
+        DynamicImage::ImageRgba8(buffer),
+        &ctx.image,
+    ))
 }
 
 pub fn render_pdf(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
-    let width = match ctx.resize_mode {
-        ResizeMode::Manual { width: Some(w), .. } => w,
-        ResizeMode::FitWidth | ResizeMode::FitTerminal => ctx.term_size.0,
-        _ => {
-            if ctx.term_size.0 > 0 {
-                ctx.term_size.0
-            } else {
-                800
-            }
-        }
+    cached_render(ctx, "pdf", &[data], || rasterize_pdf(ctx, data))
+}
+
+/// Copies the selected 0-indexed pages of a PDF document into a new one, all pages if `None`.
+pub fn select_pdf_pages(
+    ctx: &KvContext,
+    data: &[u8],
+    page_indices: Option<&[u16]>,
+) -> Result<Vec<u8>> {
+    let Some(page_indices) = page_indices else {
+        return Ok(data.to_vec());
     };
 
-    let pdfium = Pdfium::new(
+    let pdfium = bind_pdfium(ctx.pdfium_path.as_deref())?;
+    let source = pdfium.load_pdf_from_byte_slice(data, None)?;
+    let n_pages = source.pages().len();
+    if page_indices.iter().any(|&i| i >= n_pages) {
+        anyhow::bail!("Page index out of range (must be <= {})", n_pages);
+    }
+
+    let mut document = pdfium.create_new_pdf()?;
+    document
+        .pages_mut()
+        .copy_pages_from_document(&source, &format_pages(page_indices), 0)?;
+    Ok(document.save_to_bytes()?)
+}
+
+/// Sizes of all pages of a PDF document in points.
+pub fn pdf_page_sizes(ctx: &KvContext, data: &[u8]) -> Result<Vec<(f32, f32)>> {
+    let pdfium = bind_pdfium(ctx.pdfium_path.as_deref())?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)?;
+    Ok(document
+        .pages()
+        .iter()
+        .map(|page| (page.width().value, page.height().value))
+        .collect())
+}
+
+/// Binds the pdfium library at `path`, or searches the default locations.
+fn bind_pdfium(path: Option<&Path>) -> Result<Pdfium> {
+    if let Some(path) = path {
+        let library = if path.is_dir() {
+            Pdfium::pdfium_platform_library_name_at_path(path)
+        } else {
+            path.to_path_buf()
+        };
+        let bindings = Pdfium::bind_to_library(&library)
+            .with_context(|| format!("Failed to load pdfium: {}", library.display()))?;
+        return Ok(Pdfium::new(bindings));
+    }
+
+    Ok(Pdfium::new(
         Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
             .or_else(|_| {
                 Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./pdfium/"))
@@ -134,7 +364,23 @@ pub fn render_pdf(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
                 ))
             })
             .or_else(|_| Pdfium::bind_to_system_library())?,
-    );
+    ))
+}
+
+fn rasterize_pdf(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+    let width = match ctx.resize_mode {
+        ResizeMode::Manual { width: Some(w), .. } => w,
+        ResizeMode::FitWidth | ResizeMode::FitTerminal => ctx.term_size.0,
+        _ => {
+            if ctx.term_size.0 > 0 {
+                ctx.term_size.0
+            } else {
+                800
+            }
+        }
+    };
+
+    let pdfium = bind_pdfium(ctx.pdfium_path.as_deref())?;
 
     let config = PdfRenderConfig::new()
         .set_target_width(width.try_into().unwrap_or(800))
@@ -166,17 +412,8 @@ pub fn render_pdf(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
         anyhow::bail!("No pages found in PDF");
     }
 
-    let max_width = images.iter().map(|img| img.width()).max().unwrap_or(0);
-    let total_height = images.iter().map(|img| img.height()).sum::<u32>();
-
-    let mut combined = RgbaImage::new(max_width, total_height);
-    let mut current_y = 0;
-    for img in images {
-        combined.copy_from(&img, 0, current_y)?;
-        current_y += img.height();
-    }
-    let data = combined.as_raw();
-    render_image(ctx, data)
+    let combined = stack_images(images)?;
+    Ok(finish_image(ctx, DynamicImage::ImageRgba8(combined)))
 }
 
 fn is_url(s: &[u8]) -> bool {
@@ -191,15 +428,201 @@ pub fn is_html(ctx: &KvContext, extension: &str, s: &[u8]) -> bool {
     ctx.input_type == InputType::Html || extension == "html" || extension == "htm" || is_url(s)
 }
 
-pub fn render_html_chrome(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+/// Local resources that may be loaded while rendering in offline mode.
+fn is_local_url(url: &str) -> bool {
+    ["file:", "data:", "blob:", "about:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+/// Converts a file URL requested by the browser back to a path.
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    // drive letters on Windows, "/C:/dir"
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => &path[1..],
+        _ => path,
+    };
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Resolves the browser executable, `None` lets `headless_chrome` download Chromium.
+fn browser_executable(options: &HtmlOptions) -> Result<Option<PathBuf>> {
+    if let Some(path) = &options.browser_path {
+        if !path.is_file() {
+            anyhow::bail!("Browser executable not found: {}", path.display());
+        }
+        return Ok(Some(path.clone()));
+    }
+
+    if options.allow_download {
+        return Ok(None);
+    }
+
+    default_executable().map(Some).map_err(|e| {
+        anyhow::anyhow!(
+            "No Chrome/Chromium executable found and downloads are disabled: {}",
+            e
+        )
+    })
+}
+
+/// Returns the browser of this process, launching or connecting to it on first use.
+fn shared_browser(options: &HtmlOptions) -> Result<Browser> {
+    let mut shared = BROWSER
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Browser lock poisoned"))?;
+
+    if let Some((browser, _)) = shared.as_ref() {
+        // the connection is closed after the idle timeout, and the browser may have crashed
+        if browser.get_version().is_ok() {
+            return Ok(browser.clone());
+        }
+        // the browser is dropped before its profile directory
+        shared.take();
+    }
+
+    let (browser, profile_dir) = launch_browser(options)?;
+    *shared = Some((browser.clone(), profile_dir));
+    Ok(browser)
+}
+
+/// Connects to the browser of `--browser-ws`, or launches one with a new profile directory.
+fn launch_browser(options: &HtmlOptions) -> Result<(Browser, Option<TempDir>)> {
+    if let Some(ws_url) = &options.ws_url {
+        let browser = Browser::connect_with_timeout(ws_url.clone(), BROWSER_IDLE_TIMEOUT)
+            .with_context(|| format!("Failed to connect to browser at {}", ws_url))?;
+        Ok((browser, None))
+    } else {
+        // a profile per process, concurrent kv processes would fight over the profile lock
+        let profiles_dir = kv_project_dirs().data_dir.join("chromium");
+        std::fs::create_dir_all(&profiles_dir)?;
+        let profile_dir = tempfile::Builder::new()
+            .prefix("profile-")
+            .tempdir_in(&profiles_dir)
+            .context("Failed to create browser profile directory")?;
+
+        let browser = Browser::new(LaunchOptions {
+            headless: true,
+            path: browser_executable(options)?,
+            user_data_dir: Some(profile_dir.path().to_path_buf()),
+            idle_browser_timeout: BROWSER_IDLE_TIMEOUT,
+            args: offline_args(options),
+            ..Default::default()
+        })?;
+        Ok((browser, Some(profile_dir)))
+    }
+}
+
+/// Launch arguments that cut the browser off the network, request interception of a tab
+/// does not see WebSockets, workers or prefetches.
+fn offline_args(options: &HtmlOptions) -> Vec<&'static OsStr> {
+    if !options.offline {
+        return Vec::new();
+    }
+    [
+        // no host names resolve, the DevTools connection does not need any
+        "--host-resolver-rules=MAP * ~NOTFOUND",
+        // IP addresses are not resolved, send them to a closed port instead, loopback included
+        "--proxy-server=127.0.0.1:9",
+        "--proxy-bypass-list=<-loopback>",
+    ]
+    .into_iter()
+    .map(OsStr::new)
+    .collect()
+}
+
+/// Shuts down the shared browser (if launched by kv) and removes its profile directory.
+pub fn close_browser() {
+    if let Ok(mut shared) = BROWSER.lock() {
+        // the browser is dropped before its profile directory
+        shared.take();
+    }
+}
+
+/// Closes the shared browser on drop, also when rendering fails early or panics.
+pub struct BrowserGuard;
+
+impl Drop for BrowserGuard {
+    fn drop(&mut self) {
+        close_browser();
+    }
+}
+
+/// Local files loaded by an HTML page, recorded by the request interception.
+type LoadedFiles = Arc<Mutex<BTreeSet<PathBuf>>>;
+
+/// Applies the JavaScript and offline options to a new tab before it navigates anywhere, and
+/// records the local files it loads into `loaded`.
+fn prepare_tab(tab: &Tab, options: &HtmlOptions, loaded: Option<&LoadedFiles>) -> Result<()> {
+    if !options.javascript {
+        tab.call_method(SetScriptExecutionDisabled { value: true })?;
+    }
+
+    if options.offline || loaded.is_some() {
+        let patterns = [RequestPattern {
+            url_pattern: Some("*".to_string()),
+            resource_Type: None,
+            request_stage: Some(RequestStage::Request),
+        }];
+        tab.enable_fetch(Some(&patterns), None)?;
+        let offline = options.offline;
+        let loaded = loaded.cloned();
+        tab.enable_request_interception(Arc::new(
+            move |_transport: Arc<Transport>, _session_id: SessionId, event: RequestPausedEvent| {
+                let url = &event.params.request.url;
+                if let (Some(loaded), Some(path)) = (&loaded, file_url_path(url)) {
+                    loaded.lock().unwrap().insert(path);
+                }
+                if !offline || is_local_url(url) {
+                    RequestPausedDecision::Continue(None)
+                } else {
+                    RequestPausedDecision::Fail(FailRequest {
+                        request_id: event.params.request_id,
+                        error_reason: ErrorReason::BlockedByClient,
+                    })
+                }
+            },
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Opens the HTML input (URL, path or markup) in a new tab of the shared browser.
+fn open_html_tab(
+    ctx: &KvContext,
+    data: &[u8],
+    loaded: Option<&LoadedFiles>,
+) -> Result<Arc<Tab>> {
     let data_str = std::str::from_utf8(data)?;
     let url: String = if is_url_str(data_str) {
         data_str.to_owned()
     } else {
         let path = PathBuf::from(data_str);
         if path.exists() {
-            let absolute_path = path.canonicalize()?;
-            format!("file://{}", absolute_path.display())
+            file_url(&path.canonicalize()?)
         } else {
             format!(
                 "data:text/html;base64,{}",
@@ -208,19 +631,160 @@ pub fn render_html_chrome(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage>
         }
     };
 
-    let user_data_dir = kv_project_dirs().data_dir.join("chromium");
-    std::fs::create_dir_all(&user_data_dir)?;
-    let browser = Browser::new(LaunchOptions {
-        headless: true,
-        path: None,
-        user_data_dir: Some(user_data_dir),
-        ..Default::default()
-    })?;
+    if ctx.html.offline && !is_local_url(&url) {
+        anyhow::bail!("Refusing to load remote URL in offline mode: {}", url);
+    }
+
+    let browser = shared_browser(&ctx.html)?;
     let tab = browser.new_tab()?;
+
+    prepare_tab(&tab, &ctx.html, loaded)?;
     tab.navigate_to(&url)?;
     tab.wait_for_element("body")?;
-    let png_data = tab.capture_screenshot(CaptureScreenshotFormatOption::Png, None, None, true)?;
-    render_image(ctx, &png_data)
+    // the input itself is not one of the files it loads, markdown is shown from a temporary file
+    if let (Some(loaded), Some(path)) = (loaded, file_url_path(&url)) {
+        loaded.lock().unwrap().remove(&path);
+    }
+    Ok(tab)
+}
+
+/// Prints the HTML input to PDF, optionally restricted to 0-indexed pages.
+pub fn print_html_pdf(ctx: &KvContext, data: &[u8], pages: Option<&[u16]>) -> Result<Vec<u8>> {
+    print_html(ctx, data, pages, None)
+}
+
+fn print_html(
+    ctx: &KvContext,
+    data: &[u8],
+    pages: Option<&[u16]>,
+    loaded: Option<&LoadedFiles>,
+) -> Result<Vec<u8>> {
+    let tab = open_html_tab(ctx, data, loaded)?;
+    let pdf_data = tab.print_to_pdf(Some(PrintToPdfOptions {
+        print_background: Some(true),
+        page_ranges: pages.map(format_pages),
+        ..Default::default()
+    }));
+    // the browser outlives this input, so do not leave the tab behind
+    let _ = tab.close(false);
+    pdf_data.context("Failed to print HTML to PDF")
+}
+
+pub fn render_html_chrome(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+    // a printed page is cached as PDF, the PDF renderer caches its raster
+    if ctx.html.print {
+        return render_pdf(ctx, &printed_html_pdf(ctx, data)?);
+    }
+
+    let Some(content) = html_cache_content(data)? else {
+        return capture_html(ctx, data, None);
+    };
+    let content: Vec<&[u8]> = content.iter().map(Vec::as_slice).collect();
+    let key = render_cache_key(ctx, "html", &content);
+    with_loaded_files(ctx, &key, |files, loaded| match files {
+        Some(files) => {
+            let content = [content.as_slice(), files].concat();
+            cached_render(ctx, "html", &content, || capture_html(ctx, data, loaded))
+        }
+        None => capture_html(ctx, data, loaded),
+    })
+}
+
+/// Returns what identifies an HTML input in the cache, `None` for URLs.
+fn html_cache_content(data: &[u8]) -> Result<Option<Vec<Vec<u8>>>> {
+    let data_str = std::str::from_utf8(data)?;
+    if is_url_str(data_str) {
+        // pages behind URLs may change at any time
+        return Ok(None);
+    }
+
+    let path = PathBuf::from(data_str);
+    if path.is_file() {
+        // relative resources are resolved against the directory of the file
+        let content = std::fs::read(&path)?;
+        let absolute_path = path.canonicalize()?;
+        let dir = absolute_path.parent().unwrap_or(&absolute_path);
+        return Ok(Some(vec![
+            content,
+            dir.to_string_lossy().as_bytes().to_vec(),
+        ]));
+    }
+
+    Ok(Some(vec![data.to_vec()]))
+}
+
+/// Renders an HTML input cached under `key` with the current content of the local files
+/// (stylesheets, images, fonts, scripts) it loaded last time, which belongs into the cache key,
+/// and records the files it loads now. Without a record of them the input is rendered uncached.
+fn with_loaded_files<T>(
+    ctx: &KvContext,
+    key: &str,
+    render: impl FnOnce(Option<&[&[u8]]>, Option<&LoadedFiles>) -> Result<T>,
+) -> Result<T> {
+    let Some(manifest) = loaded_files_manifest(ctx, key) else {
+        return render(None, None);
+    };
+    let files = read_loaded_files(&manifest);
+    let files: Option<Vec<&[u8]>> = files
+        .as_ref()
+        .map(|files| files.iter().map(Vec::as_slice).collect());
+
+    let loaded = LoadedFiles::default();
+    let result = render(files.as_deref(), Some(&loaded))?;
+
+    // a cache hit loads nothing and keeps the files recorded before.
+    // A failing cache must not fail the render.
+    let loaded = loaded.lock().unwrap();
+    let stored = if files.is_none() || !loaded.is_empty() {
+        write_loaded_files(&manifest, &loaded).and_then(|_| evict_cache(ctx))
+    } else {
+        Ok(())
+    };
+    if let Err(e) = stored {
+        eprintln!("Warning: Failed to cache render: {:#}", e);
+    }
+    Ok(result)
+}
+
+/// Hashes the content of a printed HTML input together with the browser options.
+fn printed_html_hash(ctx: &KvContext, content: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in content {
+        // length prefix, so parts cannot shift into each other
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.update(format!("{:?}", ctx.html).as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Prints all pages of an HTML input to PDF, cached by its content, the local files it loads
+/// and the browser options.
+fn printed_html_pdf(ctx: &KvContext, data: &[u8]) -> Result<Vec<u8>> {
+    let Some(content) = html_cache_content(data)? else {
+        return print_html_pdf(ctx, data, None);
+    };
+    let content: Vec<&[u8]> = content.iter().map(Vec::as_slice).collect();
+
+    let key = printed_html_hash(ctx, &content);
+    with_loaded_files(ctx, &key, |files, loaded| match files {
+        Some(files) => {
+            let hash_str = printed_html_hash(ctx, &[content.as_slice(), files].concat());
+            cached_pdf(ctx, &hash_str, || print_html(ctx, data, None, loaded))
+        }
+        None => print_html(ctx, data, None, loaded),
+    })
+}
+
+fn capture_html(
+    ctx: &KvContext,
+    data: &[u8],
+    loaded: Option<&LoadedFiles>,
+) -> Result<DynamicImage> {
+    let tab = open_html_tab(ctx, data, loaded)?;
+    let png_data = tab.capture_screenshot(CaptureScreenshotFormatOption::Png, None, None, true);
+    let _ = tab.close(false);
+    render_image(ctx, &png_data?)
 }
 
 #[cfg(target_os = "windows")]
@@ -228,72 +792,313 @@ mod win;
 #[cfg(target_os = "windows")]
 use win as sys;
 
+/// Returns the PDF export filter for an office document and the pages to render.
+/// Spreadsheets are exported with one page per sheet, presentations with one page per slide
+/// (including hidden ones), so pages are sheet and slide numbers.
+fn office_export(
+    ctx: &KvContext,
+    data: &[u8],
+    extension: &str,
+) -> Result<(Option<String>, Option<Vec<u16>>)> {
+    if ctx.office.notes && !PRESENTATION_EXTENSIONS.contains(&extension) {
+        anyhow::bail!("Notes pages are only available for presentations");
+    }
+
+    if SPREADSHEET_EXTENSIONS.contains(&extension) {
+        let filter = r#"calc_pdf_Export:{"SinglePageSheets":{"type":"boolean","value":"true"}}"#;
+        let page_indices = match &ctx.office.sheet {
+            Some(sheet) => {
+                let names = spreadsheet_sheet_names(data);
+                Some(vec![resolve_sheet(sheet, names.as_deref())?])
+            }
+            None => ctx.page_indices.clone(),
+        };
+        return Ok((Some(filter.to_string()), page_indices));
+    }
+
+    if ctx.office.sheet.is_some() {
+        anyhow::bail!("Sheet selection is only available for spreadsheets");
+    }
+
+    if PRESENTATION_EXTENSIONS.contains(&extension) {
+        let filter = format!(
+            r#"impress_pdf_Export:{{"ExportHiddenSlides":{{"type":"boolean","value":"true"}},"ExportNotesPages":{{"type":"boolean","value":"{0}"}},"ExportOnlyNotesPages":{{"type":"boolean","value":"{0}"}}}}"#,
+            ctx.office.notes
+        );
+        return Ok((Some(filter), ctx.page_indices.clone()));
+    }
+
+    Ok((None, ctx.page_indices.clone()))
+}
+
 pub fn render_office(ctx: &KvContext, data: &[u8], extension: &str) -> Result<DynamicImage> {
-    let hash = Sha256::digest(data);
-    let hash_str = hex::encode(hash);
+    let (pdf_data, page_indices) = convert_office(ctx, data, extension)?;
+    let ctx = &KvContext {
+        page_indices,
+        ..ctx.clone()
+    };
+    render_pdf(ctx, &pdf_data)
+}
 
-    // convert to pdf with libreoffice (soffice command)
-    let project_dirs = kv_project_dirs();
-    let temp_dir_guard = tempfile::tempdir()?; // Keep guard alive
+/// Converts an office document to PDF with LibreOffice, cached by its content and export
+/// filter. Returns the PDF and the pages to show, which select a sheet with `--sheet`.
+pub fn convert_office(
+    ctx: &KvContext,
+    data: &[u8],
+    extension: &str,
+) -> Result<(Vec<u8>, Option<Vec<u16>>)> {
+    let (export_filter, page_indices) = office_export(ctx, data, extension)?;
+
+    // the export filter changes the PDF, so it is part of the cached name
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    if let Some(filter) = &export_filter {
+        hasher.update(filter.as_bytes());
+    }
+    let hash_str = hex::encode(hasher.finalize());
+
+    let pdf_data = cached_pdf(ctx, &hash_str, || {
+        // convert to pdf with libreoffice (soffice command)
+        let temp_dir_guard = tempfile::tempdir()?; // Keep guard alive
+        let target_dir = temp_dir_guard.path();
+
+        // create temp file with name hash.extension, only the PDF is kept in the cache
+        let source_temp = target_dir.join(format!("{}.{}", hash_str, extension));
+        std::fs::write(&source_temp, data)?;
+
+        let (profile, _profile_lock) = office_profile(target_dir);
+
+        eprintln!("Converting office document to PDF...");
+        let soffice = ctx
+            .office
+            .soffice_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("soffice"));
+        let mut cmd = Command::new(&soffice);
+        cmd
+            // a separate profile, an open LibreOffice instance would swallow the conversion
+            .arg(format!("-env:UserInstallation={}", file_url(&profile)))
+            .arg("--headless")
+            .arg("--norestore")
+            // import delimited text as spreadsheet (separator, text delimiter, UTF-8)
+            .args(match extension {
+                "csv" => vec!["--infilter=CSV:44,34,76"],
+                "tsv" => vec!["--infilter=CSV:9,34,76"],
+                _ => vec![],
+            })
+            .arg("--convert-to")
+            .arg(match &export_filter {
+                Some(filter) => format!("pdf:{}", filter),
+                None => "pdf".to_string(),
+            })
+            .arg(source_temp.as_os_str())
+            .arg("--outdir")
+            .arg(target_dir.as_os_str())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // soffice starts soffice.bin, which has to be killed on timeout as well
+        isolate_process_group(&mut cmd, ctx.office.timeout);
+        let child = cmd
+            .spawn()
+            .context("Failed to run soffice (is LibreOffice installed?)")?;
+
+        let output = wait_with_timeout(child, ctx.office.timeout)
+            .context("Failed to convert office document to PDF")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "soffice exited with error code {:?}: {}",
+                output.status.code(),
+                output_message(&output)
+            );
+        }
 
-    let (target_dir, is_persistent) = match &ctx.cache_mode {
-        CacheMode::Disabled => (temp_dir_guard.path().to_path_buf(), false),
-        CacheMode::Default => (project_dirs.cache_dir.clone(), true),
-        CacheMode::Custom(path) => (path.clone(), true),
+        let pdf_path = target_dir.join(format!("{}.pdf", hash_str));
+        if !pdf_path.exists() {
+            anyhow::bail!("soffice did not produce a PDF: {}", output_message(&output));
+        }
+        Ok(std::fs::read(&pdf_path)?)
+    })?;
+
+    Ok((pdf_data, page_indices))
+}
+
+/// Returns the PDF cached under `hash`, or produces and caches it.
+fn cached_pdf(
+    ctx: &KvContext,
+    hash: &str,
+    produce: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let Some(dir) = cache_dir(&ctx.cache_mode) else {
+        return produce();
+    };
+    let cache_path = dir.join(format!("{}.pdf", hash));
+    if let Some(pdf_data) = read_cached_pdf(&cache_path) {
+        return Ok(pdf_data);
+    }
+
+    // another process may be producing the same PDF, wait for it and check again.
+    // A failing cache must not fail the render.
+    let lock = match lock_cache_entry(&dir, hash) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("Warning: Failed to cache PDF: {:#}", e);
+            return produce();
+        }
     };
+    if let Some(pdf_data) = read_cached_pdf(&cache_path) {
+        return Ok(pdf_data);
+    }
 
-    if is_persistent {
-        std::fs::create_dir_all(&target_dir).context("Failed to create cache directory")?;
+    let pdf_data = produce()?;
+    let stored = write_cache_file(&cache_path, &pdf_data);
+    drop(lock);
+    if let Err(e) = stored.and_then(|_| evict_cache(ctx)) {
+        eprintln!("Warning: Failed to cache PDF: {:#}", e);
+    }
+    Ok(pdf_data)
+}
+
+/// Reads a cached PDF and marks it as used.
+fn read_cached_pdf(path: &Path) -> Option<Vec<u8>> {
+    let pdf_data = std::fs::read(path).ok()?;
+    touch_cache_entry(path);
+    Some(pdf_data)
+}
+
+/// Returns the LibreOffice profile for a conversion, and the lock reserving it.
+/// A profile is used by one soffice instance at a time, a second instance hands its conversion
+/// to the running one and exits without output. While another kv process uses the shared
+/// profile, a temporary profile in `temp_dir` is used instead.
+fn office_profile(temp_dir: &Path) -> (PathBuf, Option<File>) {
+    let data_dir = kv_project_dirs().data_dir;
+    let profile = data_dir.join("libreoffice");
+    let lock = std::fs::create_dir_all(&data_dir)
+        .and_then(|_| File::create(profile.with_extension("lock")))
+        .ok()
+        .filter(|file| file.try_lock().is_ok());
+
+    match lock {
+        Some(lock) => (profile, Some(lock)),
+        None => (temp_dir.join("libreoffice"), None),
+    }
+}
 
-        // check if cached PDF already exists
-        let cache_path = target_dir.join(format!("{}.pdf", hash_str));
-        if cache_path.exists() {
-            let cache_data = std::fs::read(&cache_path)?;
-            return render_pdf(ctx, &cache_data);
+/// Values of the render context placeholders available in plugin commands.
+fn plugin_placeholders(ctx: &KvContext, extension: &str) -> Vec<(&'static str, String)> {
+    // the box the image is fit into, the terminal size unless set explicitly
+    let (width, height) = match ctx.resize_mode {
+        ResizeMode::Manual { width, height } => (
+            width.unwrap_or(ctx.term_size.0),
+            height.unwrap_or(ctx.term_size.1),
+        ),
+        _ => ctx.term_size,
+    };
+    let first_page = ctx
+        .page_indices
+        .as_ref()
+        .and_then(|indices| indices.first())
+        .map_or(0, |index| *index);
+    let filename = ctx
+        .source
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    vec![
+        ("{width}", width.to_string()),
+        ("{height}", height.to_string()),
+        (
+            "{pages}",
+            ctx.page_indices
+                .as_deref()
+                .map(format_pages)
+                .unwrap_or_default(),
+        ),
+        ("{page}", (first_page + 1).to_string()),
+        (
+            "{bg}",
+            ctx.background_color
+                .map(|color| format!("#{:02X}{:02X}{:02X}", color[0], color[1], color[2]))
+                .unwrap_or_default(),
+        ),
+        ("{ext}", extension.to_string()),
+        ("{filename}", filename),
+    ]
+}
+
+/// Replaces placeholders in a single pass, so substituted values are never replaced again.
+/// `placeholders` must be sorted by descending length and `found` marks the replaced ones.
+fn replace_placeholders(arg: &str, placeholders: &[(&str, String)], found: &mut [bool]) -> String {
+    let mut result = String::new();
+    let mut rest = arg;
+    'outer: while let Some(c) = rest.chars().next() {
+        for (i, (placeholder, value)) in placeholders.iter().enumerate() {
+            if !placeholder.is_empty() && rest.starts_with(placeholder) {
+                result.push_str(value);
+                rest = &rest[placeholder.len()..];
+                found[i] = true;
+                continue 'outer;
+            }
         }
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
     }
+    result
+}
 
-    // create temp file with name hash.extension
-    let source_temp = target_dir.join(format!("{}.{}", hash_str, extension));
-    std::fs::write(&source_temp, data)?;
-
-    eprintln!("Converting office document to PDF...");
-    // mute soffice output
-    Command::new("soffice")
-        .arg("--headless")
-        .arg("--convert-to")
-        .arg("pdf")
-        .arg(source_temp.as_os_str())
-        .arg("--outdir")
-        .arg(target_dir.as_os_str())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .context("Failed to convert office document to PDF")?;
-
-    let pdf_path = target_dir.join(format!("{}.pdf", hash_str));
-    let pdf_data = std::fs::read(&pdf_path)?;
-    render_pdf(ctx, &pdf_data)
+/// Splits concatenated PNG images, e.g. frames written to a pipe. Other data is returned as is.
+fn split_png_stream(data: &[u8]) -> Vec<&[u8]> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    let mut images = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        if !data[start..].starts_with(PNG_SIGNATURE) {
+            return vec![data];
+        }
+
+        // chunks consist of length, type, data and CRC, the last chunk is IEND
+        let mut pos = start + PNG_SIGNATURE.len();
+        loop {
+            let Some(header) = data.get(pos..pos + 8) else {
+                return vec![data];
+            };
+            let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            pos += 12 + length;
+            if &header[4..8] == b"IEND" {
+                break;
+            }
+        }
+        if pos > data.len() {
+            return vec![data];
+        }
+
+        images.push(&data[start..pos]);
+        start = pos;
+    }
+    images
 }
 
-pub fn render_plugin(ctx: &KvContext, data: &[u8], plugin: &Plugin) -> Result<DynamicImage> {
+/// Runs a plugin and returns its outputs: the files of an output directory in name order,
+/// the images of a PNG stream, or the single output.
+pub fn run_plugin(
+    ctx: &KvContext,
+    data: &[u8],
+    extension: &str,
+    plugin: &Plugin,
+) -> Result<Vec<Vec<u8>>> {
+    plugin.validate()?;
+
     let temp_dir_guard = tempfile::tempdir()?;
     let mut command_parts =
         shell_words::split(&plugin.path).context("Invalid command string in plugin config")?;
 
-    if command_parts.is_empty() {
-        anyhow::bail!("Plugin command is empty");
-    }
-
     let program = command_parts.remove(0);
     let mut cmd = Command::new(program);
 
-    if let (Some(i), Some(o)) = (&plugin.placeholder, &plugin.output_placeholder) {
-        if i == o {
-            anyhow::bail!("Input placeholder equals output placeholder");
-        }
-    }
-
     let mut input_path_opts = None;
     if let Some(placeholder) = &plugin.placeholder {
         let path = temp_dir_guard.path().join("input_tmp");
@@ -304,85 +1109,109 @@ pub fn render_plugin(ctx: &KvContext, data: &[u8], plugin: &Plugin) -> Result<Dy
     let mut output_path_opts = None;
     if let Some(output_placeholder) = &plugin.output_placeholder {
         let path = temp_dir_guard.path().join("output_tmp");
+        if plugin.output_dir {
+            std::fs::create_dir(&path)?;
+        }
         output_path_opts = Some((
             output_placeholder.clone(),
             path.to_string_lossy().to_string(),
         ));
     }
 
-    let mut replaced_input = false;
-    let mut replaced_output = false;
-    let mut replacements = Vec::new();
-    if let Some((p, path)) = &input_path_opts {
-        replacements.push((p, path, &mut replaced_input));
+    let mut replacements: Vec<(&str, String)> = Vec::new();
+    for (p, path) in input_path_opts.iter().chain(&output_path_opts) {
+        replacements.push((p.as_str(), path.clone()));
     }
-    if let Some((p, path)) = &output_path_opts {
-        replacements.push((p, path, &mut replaced_output));
+    // render context placeholders, unless used for the input or output file
+    for (p, value) in plugin_placeholders(ctx, extension) {
+        if replacements.iter().all(|(existing, _)| *existing != p) {
+            replacements.push((p, value));
+        }
     }
 
     // This ensures "{{}}" is checked before "{}" automatically
-    replacements.sort_by(|(p1, _, _), (p2, _, _)| p2.len().cmp(&p1.len()));
+    replacements.sort_by_key(|(p, _)| std::cmp::Reverse(p.len()));
 
+    let mut replaced = vec![false; replacements.len()];
     for arg in command_parts {
-        let mut final_arg = arg;
-        for (placeholder, path, flag) in replacements.iter_mut() {
-            if final_arg.contains(*placeholder) {
-                final_arg = final_arg.replace(*placeholder, path);
-                **flag = true;
-            }
-        }
-        cmd.arg(final_arg);
+        cmd.arg(replace_placeholders(&arg, &replacements, &mut replaced));
     }
+    let was_replaced = |placeholder: &str| {
+        replacements
+            .iter()
+            .zip(&replaced)
+            .any(|((p, _), replaced)| *p == placeholder && *replaced)
+    };
 
-    if input_path_opts.is_some() && !replaced_input {
+    if let Some((p, _)) = &input_path_opts
+        && !was_replaced(p)
+    {
         anyhow::bail!("Input placeholder not found in arguments");
     }
-    if output_path_opts.is_some() && !replaced_output {
+    if let Some((p, _)) = &output_path_opts
+        && !was_replaced(p)
+    {
         anyhow::bail!("Output placeholder not found in arguments");
     }
 
+    cmd.envs(&plugin.env);
+    if let Some(cwd) = &plugin.cwd {
+        cmd.current_dir(cwd);
+    }
+
     // pipe outputs correctly
     if input_path_opts.is_none() {
         cmd.stdin(Stdio::piped());
+    } else if plugin.stdin_closed {
+        cmd.stdin(Stdio::null());
     }
     if output_path_opts.is_none() {
         cmd.stdout(Stdio::piped());
     }
-    cmd.stderr(Stdio::inherit());
+    cmd.stderr(Stdio::piped());
+    isolate_process_group(&mut cmd, plugin.timeout());
 
     let mut child = cmd.spawn().context("Failed to spawn plugin command")?;
 
     if input_path_opts.is_none() {
-        let mut stdin = child.stdin.take().context("Failed to open stdin")?;
-        stdin
-            .write_all(data)
-            .context("Failed to write to plugin stdin")?;
-        drop(stdin); // Close stdin to assure no more data
+        write_stdin(&mut child, data)?;
     }
 
-    let output = child
-        .wait_with_output()
-        .context("Plugin execution failed")?;
+    let output = wait_with_timeout(child, plugin.timeout()).context("Plugin execution failed")?;
 
     if !output.status.success() {
-        anyhow::bail!("Plugin exited with error code: {:?}", output.status.code());
+        anyhow::bail!(
+            "Plugin exited with error code {:?}: {}",
+            output.status.code(),
+            output_message(&output)
+        );
     }
 
-    let output_data = if let Some((_, path)) = output_path_opts {
-        std::fs::read(path).context("Failed to read plugin output file")?
-    } else {
-        output.stdout
+    let outputs = match output_path_opts {
+        Some((_, path)) if plugin.output_dir => {
+            let mut paths = std::fs::read_dir(&path)
+                .context("Failed to read plugin output directory")?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<Result<Vec<_>>>()?;
+            paths.retain(|path| path.is_file());
+            paths.sort();
+            paths
+                .iter()
+                .map(|path| std::fs::read(path).context("Failed to read plugin output file"))
+                .collect::<Result<Vec<_>>>()?
+        }
+        Some((_, path)) => vec![std::fs::read(path).context("Failed to read plugin output file")?],
+        None if plugin.output == InputType::Image => split_png_stream(&output.stdout)
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect(),
+        None => vec![output.stdout],
     };
 
-    // if output is empty raise error
-    if output_data.is_empty() {
+    // if output is empty raise error, an output directory may also be left empty
+    if outputs.is_empty() || outputs.iter().all(|output| output.is_empty()) {
         anyhow::bail!("Plugin returned no output");
     }
 
-    match plugin.output {
-        InputType::Svg => render_svg(ctx, &output_data),
-        InputType::Pdf => render_pdf(ctx, &output_data),
-        InputType::Html => render_html_chrome(ctx, &output_data),
-        _ => Ok(render_image(ctx, &output_data).context("Failed to decode plugin output as image")?),
-    }
+    Ok(outputs)
 }
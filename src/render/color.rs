@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageBuffer, RgbImage, RgbaImage};
+use moxcms::{CmsError, ColorProfile, DataColorSpace, Layout, TransformOptions};
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+/// Header of a JPEG image with four color components (CMYK or YCCK).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CmykJpeg {
+    pub width: u32,
+    pub height: u32,
+    /// Written by Adobe applications, which store inverted values (0 is full ink).
+    pub adobe: bool,
+    /// Colors stored as YCbCr of the inverted CMY values, black as is (Adobe transform 2).
+    pub ycck: bool,
+}
+
+fn cms_error(e: CmsError) -> anyhow::Error {
+    anyhow::anyhow!("{:?}", e)
+}
+
+/// Reads the markers of a JPEG image up to its frame header, `None` unless it is CMYK.
+pub fn cmyk_jpeg_header(data: &[u8]) -> Option<CmykJpeg> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut offset = 2;
+    let mut adobe = false;
+    let mut ycck = false;
+    while let Some(&[0xFF, marker, high, low, ..]) = data.get(offset..) {
+        let length = u16::from_be_bytes([high, low]) as usize;
+        let segment = data.get(offset + 4..offset + 2 + length.max(2))?;
+        match marker {
+            0xEE if segment.starts_with(b"Adobe") => {
+                adobe = true;
+                // after the version and flags
+                ycck = segment.get(11) == Some(&2);
+            }
+            // start of frame, except the huffman and arithmetic coding tables
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let &[_, h1, h0, w1, w0, components, ..] = segment else {
+                    return None;
+                };
+                return (components == 4).then(|| CmykJpeg {
+                    width: u16::from_be_bytes([w1, w0]).into(),
+                    height: u16::from_be_bytes([h1, h0]).into(),
+                    adobe,
+                    ycck,
+                });
+            }
+            // start of scan, the frame header always comes before
+            0xDA => return None,
+            _ => {}
+        }
+        offset += 2 + length.max(2);
+    }
+    None
+}
+
+/// Converts YCCK values to CMYK in place, as inverted values like those Adobe stores.
+pub fn ycck_to_cmyk(ycck: &mut [u8]) {
+    for pixel in ycck.chunks_exact_mut(4) {
+        let y = pixel[0] as f32;
+        let cb = pixel[1] as f32 - 128.0;
+        let cr = pixel[2] as f32 - 128.0;
+        let rgb = [
+            y + 1.402 * cr,
+            y - 0.344_136 * cb - 0.714_136 * cr,
+            y + 1.772 * cb,
+        ];
+        // black is kept in the fourth channel
+        for (value, channel) in pixel.iter_mut().zip(rgb) {
+            *value = 255 - channel.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Converts CMYK values (0 is no ink) to RGB without a color profile.
+pub fn cmyk_to_rgb(cmyk: &[u8]) -> Vec<u8> {
+    cmyk.chunks_exact(4)
+        .flat_map(|pixel| {
+            let white = 255 - pixel[3] as u32;
+            [0, 1, 2].map(|i| ((255 - pixel[i] as u32) * white / 255) as u8)
+        })
+        .collect()
+}
+
+fn cmyk_to_srgb(cmyk: &[u8], icc_profile: &[u8]) -> Result<Vec<u8>> {
+    let source = ColorProfile::new_from_slice(icc_profile).map_err(cms_error)?;
+    if source.color_space != DataColorSpace::Cmyk {
+        anyhow::bail!("Not a CMYK profile");
+    }
+    // CMYK is read with the layout of four channels
+    let transform = source
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgb,
+            TransformOptions::default(),
+        )
+        .map_err(cms_error)?;
+    let mut rgb = vec![0; cmyk.len() / 4 * 3];
+    transform.transform(cmyk, &mut rgb).map_err(cms_error)?;
+    Ok(rgb)
+}
+
+/// Decodes a CMYK JPEG image to sRGB, with its embedded color profile if there is one.
+pub fn decode_cmyk_jpeg(
+    data: &[u8],
+    header: &CmykJpeg,
+    icc_profile: Option<&[u8]>,
+) -> Result<DynamicImage> {
+    // the decoder cannot convert YCCK to CMYK, so it returns the values as stored
+    let colorspace = if header.ycck {
+        ColorSpace::YCCK
+    } else {
+        ColorSpace::CMYK
+    };
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let mut cmyk = decoder
+        .decode()
+        .map_err(|e| anyhow::anyhow!("Failed to decode CMYK JPEG: {:?}", e))?;
+    if header.ycck {
+        ycck_to_cmyk(&mut cmyk);
+    }
+    if header.adobe {
+        for value in &mut cmyk {
+            *value = 255 - *value;
+        }
+    }
+
+    let rgb = match icc_profile.map(|profile| cmyk_to_srgb(&cmyk, profile)) {
+        Some(Ok(rgb)) => rgb,
+        Some(Err(e)) => {
+            eprintln!("Warning: Ignoring the ICC profile of a CMYK JPEG: {:#}", e);
+            cmyk_to_rgb(&cmyk)
+        }
+        None => cmyk_to_rgb(&cmyk),
+    };
+    let buffer = RgbImage::from_raw(header.width, header.height, rgb)
+        .context("Failed to decode CMYK JPEG: invalid image size")?;
+    Ok(DynamicImage::ImageRgb8(buffer))
+}
+
+/// Checks that a profile describes sRGB by its primaries and tone curves, the names of sRGB
+/// profiles vary and other profiles may be named alike.
+pub fn is_srgb(profile: &ColorProfile) -> bool {
+    let srgb = ColorProfile::new_srgb();
+    // lookup tables take precedence over the curves and colorants
+    let has_lut = [
+        &profile.lut_a_to_b_perceptual,
+        &profile.lut_a_to_b_colorimetric,
+        &profile.lut_a_to_b_saturation,
+    ]
+    .iter()
+    .any(|lut| lut.is_some());
+    if !profile.is_matrix_shaper() || has_lut {
+        return false;
+    }
+
+    // colorants are stored as s15Fixed16 numbers, adapted to D50 like those of `new_srgb`
+    let colorants = |p: &ColorProfile| [p.red_colorant, p.green_colorant, p.blue_colorant];
+    let same_primaries = colorants(profile)
+        .iter()
+        .zip(colorants(&srgb))
+        .all(|(a, b)| [a.x - b.x, a.y - b.y, a.z - b.z].iter().all(|d| d.abs() < 1e-3));
+
+    // curves are parametric or tables of 16-bit values, compared where they are evaluated
+    let Some(Ok(srgb_curve)) = srgb.red_trc.as_ref().map(|trc| trc.make_linear_evaluator()) else {
+        return false;
+    };
+    let same_curves = [&profile.red_trc, &profile.green_trc, &profile.blue_trc]
+        .iter()
+        .all(|trc| match trc.as_ref().map(|trc| trc.make_linear_evaluator()) {
+            Some(Ok(curve)) => (0..=64).all(|i| {
+                let value = i as f32 / 64.0;
+                (curve.evaluate_value(value) - srgb_curve.evaluate_value(value)).abs() < 2e-3
+            }),
+            _ => false,
+        });
+
+    same_primaries && same_curves
+}
+
+/// Transforms a decoded image from its ICC profile to sRGB, `None` if it already is sRGB.
+/// Images with 16-bit channels are transformed with 16 bits, others with 8 bits.
+fn transform_to_srgb(img: &DynamicImage, icc_profile: &[u8]) -> Result<Option<DynamicImage>> {
+    let source = ColorProfile::new_from_slice(icc_profile).map_err(cms_error)?;
+    if is_srgb(&source) {
+        return Ok(None);
+    }
+    let layout = match source.color_space {
+        DataColorSpace::Rgb => Layout::Rgba,
+        DataColorSpace::Gray => Layout::GrayAlpha,
+        color_space => anyhow::bail!("Unsupported color space {:?}", color_space),
+    };
+    let srgb = ColorProfile::new_srgb();
+    let (width, height) = (img.width(), img.height());
+    let len = width as usize * height as usize * 4;
+
+    let color = img.color();
+    if color.bytes_per_pixel() > color.channel_count() {
+        let pixels = match layout {
+            Layout::Rgba => img.to_rgba16().into_raw(),
+            _ => img.to_luma_alpha16().into_raw(),
+        };
+        let transform = source
+            .create_transform_16bit(layout, &srgb, Layout::Rgba, TransformOptions::default())
+            .map_err(cms_error)?;
+        let mut rgba = vec![0; len];
+        transform.transform(&pixels, &mut rgba).map_err(cms_error)?;
+        let buffer = ImageBuffer::from_raw(width, height, rgba).context("Invalid image size")?;
+        return Ok(Some(DynamicImage::ImageRgba16(buffer)));
+    }
+
+    let pixels = match layout {
+        Layout::Rgba => img.to_rgba8().into_raw(),
+        _ => img.to_luma_alpha8().into_raw(),
+    };
+    let transform = source
+        .create_transform_8bit(layout, &srgb, Layout::Rgba, TransformOptions::default())
+        .map_err(cms_error)?;
+    let mut rgba = vec![0; len];
+    transform.transform(&pixels, &mut rgba).map_err(cms_error)?;
+    let buffer = RgbaImage::from_raw(width, height, rgba).context("Invalid image size")?;
+    Ok(Some(DynamicImage::ImageRgba8(buffer)))
+}
+
+/// Converts a decoded image from its embedded ICC profile to sRGB, the color space terminals
+/// assume. Images in sRGB are returned as is, also those with invalid profiles.
+pub fn convert_to_srgb(img: DynamicImage, icc_profile: &[u8]) -> DynamicImage {
+    match transform_to_srgb(&img, icc_profile) {
+        Ok(Some(converted)) => converted,
+        Ok(None) => img,
+        Err(e) => {
+            eprintln!("Warning: Ignoring the ICC profile: {:#}", e);
+            img
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use bat::assets::HighlightingAssets;
+use image::DynamicImage;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::io::Write;
+use std::path::Path;
+use syntect::html::highlighted_html_for_string;
+
+use crate::{HtmlOptions, KvContext, MarkdownTheme, print_html_pdf, render_html_chrome};
+
+const LIGHT_CSS: &str = "
+body { color: #1f2328; background: #ffffff; }
+a { color: #0969da; }
+code { background: #eff1f3; }
+blockquote { color: #59636e; border-left-color: #d1d9e0; }
+th, td { border-color: #d1d9e0; }
+hr { background: #d1d9e0; }
+";
+
+const DARK_CSS: &str = "
+body { color: #e6edf3; background: #0d1117; }
+a { color: #4493f8; }
+code { background: #262c36; }
+blockquote { color: #9198a1; border-left-color: #3d444d; }
+th, td { border-color: #3d444d; }
+hr { background: #3d444d; }
+";
+
+const BASE_CSS: &str = "
+body {
+    font-family: -apple-system, 'Segoe UI', 'Noto Sans', Helvetica, Arial, sans-serif;
+    font-size: 16px;
+    line-height: 1.5;
+    max-width: 880px;
+    margin: 0 auto;
+    padding: 32px;
+}
+code, pre { font-family: ui-monospace, Menlo, Consolas, 'DejaVu Sans Mono', monospace; font-size: 85%; }
+code { padding: 0.2em 0.4em; border-radius: 6px; }
+pre { padding: 16px; overflow: auto; border-radius: 6px; line-height: 1.45; }
+pre code { padding: 0; background: transparent; font-size: 100%; }
+img { max-width: 100%; }
+blockquote { margin: 0; padding: 0 1em; border-left: 0.25em solid; }
+table { border-collapse: collapse; }
+th, td { padding: 6px 13px; border: 1px solid; }
+hr { height: 0.25em; border: 0; }
+";
+
+impl MarkdownTheme {
+    fn css(self) -> &'static str {
+        match self {
+            MarkdownTheme::Light => LIGHT_CSS,
+            MarkdownTheme::Dark => DARK_CSS,
+        }
+    }
+
+    /// Theme of the bundled bat assets used for code blocks.
+    fn code_theme(self) -> &'static str {
+        match self {
+            MarkdownTheme::Light => "OneHalfLight",
+            MarkdownTheme::Dark => "OneHalfDark",
+        }
+    }
+}
+
+/// Converts markdown to a standalone HTML document with highlighted code blocks.
+/// Relative links and images are resolved against `base_dir`.
+pub fn markdown_to_html(markdown: &str, theme: MarkdownTheme, base_dir: &Path) -> Result<String> {
+    let assets = HighlightingAssets::from_binary();
+    let syntax_set = assets
+        .get_syntax_set()
+        .map_err(|e| anyhow::anyhow!("Failed to load syntaxes: {}", e))?;
+    let code_theme = assets.get_theme(theme.code_theme());
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+
+    let mut events = Vec::new();
+    // language and content of the currently open code block
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((language, String::new()));
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = &mut code_block {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let (language, code) = code_block.take().unwrap_or_default();
+                let syntax = syntax_set
+                    .find_syntax_by_token(&language)
+                    .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+                let html = highlighted_html_for_string(&code, syntax_set, syntax, code_theme)
+                    .context("Failed to highlight code block")?;
+                events.push(Event::Html(CowStr::from(html)));
+            }
+            event => events.push(event),
+        }
+    }
+
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, events.into_iter());
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<base href=\"{}/\">\n<style>{}{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        file_url(base_dir),
+        BASE_CSS,
+        theme.css(),
+        body
+    ))
+}
+
+/// Converts an absolute path to a file URL. Everything but unreserved characters and separators
+/// is percent-encoded, which also makes the URL safe to use in an HTML attribute.
+pub fn file_url(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    // verbatim paths on Windows, as returned by canonicalize
+    let bytes = bytes.strip_prefix(br"\\?\").unwrap_or(bytes);
+    let mut url = String::from("file://");
+    if !bytes.starts_with(b"/") {
+        url.push('/');
+    }
+    for &byte in bytes {
+        match byte {
+            b'\\' if cfg!(windows) => url.push('/'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b':' | b'-' | b'.' | b'_' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// Writes markdown as HTML page and passes its path to the HTML pipeline. A browser is never
+/// downloaded just for markdown, which is shown as text instead.
+fn with_html_page<T>(
+    ctx: &KvContext,
+    data: &[u8],
+    render: impl FnOnce(&KvContext, &[u8]) -> Result<T>,
+) -> Result<T> {
+    let markdown = std::str::from_utf8(data).context("Markdown is not valid UTF-8")?;
+
+    // local images are referenced relative to the markdown file
+    let base_dir = match ctx.source.as_ref().and_then(|path| path.parent()) {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => std::env::current_dir()?,
+    };
+    let html = markdown_to_html(markdown, ctx.markdown_theme, &base_dir)?;
+
+    // a file URL is required, a data URL may not load local images
+    let mut html_file = tempfile::Builder::new()
+        .prefix("kv-markdown-")
+        .suffix(".html")
+        .tempfile()?;
+    html_file.write_all(html.as_bytes())?;
+    html_file.flush()?;
+
+    let ctx = KvContext {
+        html: HtmlOptions {
+            allow_download: false,
+            ..ctx.html.clone()
+        },
+        ..ctx.clone()
+    };
+    let path = html_file.path().to_string_lossy().to_string();
+    render(&ctx, path.as_bytes())
+}
+
+pub fn render_markdown(ctx: &KvContext, data: &[u8]) -> Result<DynamicImage> {
+    with_html_page(ctx, data, render_html_chrome)
+}
+
+/// Prints rendered markdown to PDF, optionally restricted to 0-indexed pages.
+pub fn print_markdown_pdf(ctx: &KvContext, data: &[u8], pages: Option<&[u16]>) -> Result<Vec<u8>> {
+    with_html_page(ctx, data, |ctx, path| print_html_pdf(ctx, path, pages))
+}
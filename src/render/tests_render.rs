@@ -1,15 +1,28 @@
 use super::*;
+use crate::{CacheMode, MarkdownTheme, OfficeOptions};
 use image::{GenericImageView, Rgba};
+use moxcms::{ColorProfile, ToneReprCurve, curve_from_gamma};
 use rstest::rstest;
 
 const SVG_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.svg");
 const PDF_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.pdf");
 const HTML_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.html");
+const PNG_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.png");
 const RANDOM_DATA: &[u8] = include_bytes!("../../tests/fixtures/test.random");
+const CMYK_JPEG: &[u8] = include_bytes!("../../tests/fixtures/cmyk.jpg");
+const YCCK_JPEG: &[u8] = include_bytes!("../../tests/fixtures/ycck.jpg");
+const DISPLAY_P3_ICC: &[u8] = include_bytes!("../../tests/fixtures/display-p3.icc");
+
+fn default_ctx() -> KvContext {
+    KvContext {
+        term_size: (100, 50),
+        ..KvContext::default()
+    }
+}
 
 #[test]
 fn test_render_svg() {
-    let result = render_svg(ctx, SVG_DATA);
+    let result = render_svg(&default_ctx(), SVG_DATA);
     assert!(result.is_ok(), "SVG generation failed");
 
     let img = result.unwrap();
@@ -24,7 +37,7 @@ fn test_render_svg() {
 fn test_render_svg_invalid() {
     let svg_data = br#"<svg>invalid"#;
 
-    let result = render_svg(ctx, svg_data);
+    let result = render_svg(&default_ctx(), svg_data);
     assert!(result.is_err(), "SVG generation failed");
 }
 
@@ -38,7 +51,19 @@ fn test_render_pdf(
     #[case] page_indices: Option<Vec<u16>>,
     #[case] expected_width: u32,
 ) {
-    let result = render_pdf(ctx, PDF_DATA);
+    let ctx = KvContext {
+        resize_mode: match conf_w {
+            Some(width) => ResizeMode::Manual {
+                width: Some(width),
+                height: None,
+            },
+            None => ResizeMode::ClipTerminal,
+        },
+        term_size: (term_width, 50),
+        page_indices,
+        ..KvContext::default()
+    };
+    let result = render_pdf(&ctx, PDF_DATA);
     assert!(result.is_ok(), "PDF generation failed");
 
     let img = result.unwrap();
@@ -53,7 +78,7 @@ fn test_render_pdf_invalid() {
     let pdf_data = br#"%PDF-1.4
 invalid"#;
 
-    let result = render_pdf(ctx, pdf_data); // , None, 100, None);
+    let result = render_pdf(&default_ctx(), pdf_data);
     assert!(result.is_err(), "PDF generation failed");
 }
 
@@ -61,16 +86,32 @@ invalid"#;
 #[case(vec![])]
 #[case(vec![2])]
 fn test_render_pdf_out_of_range(#[case] page_indices: Vec<u16>) {
-    let result = render_pdf(ctx, PDF_DATA); // , None, 100, Some(page_indices));
+    let ctx = KvContext {
+        page_indices: Some(page_indices),
+        ..default_ctx()
+    };
+    let result = render_pdf(&ctx, PDF_DATA);
     assert!(result.is_err(), "PDF generation failed");
 }
 
+#[test]
+fn test_cached_pdf_broken_cache() {
+    // the cache directory cannot be created below a file
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let ctx = KvContext {
+        cache_mode: CacheMode::Custom(file.path().join("cache")),
+        ..default_ctx()
+    };
+    let pdf_data = cached_pdf(&ctx, "a".repeat(64).as_str(), || Ok(PDF_DATA.to_vec()));
+    assert_eq!(pdf_data.unwrap(), PDF_DATA);
+}
+
 #[rstest]
 #[case(HTML_DATA)]
-#[case(b"fixtures/test.html")]
+#[case(b"tests/fixtures/test.html")]
 #[case(b"https://commons.wikimedia.org/wiki/File:Solid_red.png")]
 fn test_render_html_chrome(#[case] html_data: &[u8]) {
-    let result = render_html_chrome(ctx, html_data);
+    let result = render_html_chrome(&default_ctx(), html_data);
     assert!(result.is_ok(), "HTML generation failed");
 
     let img = result.unwrap();
@@ -92,6 +133,487 @@ fn test_render_html_chrome(#[case] html_data: &[u8]) {
 #[rstest]
 #[case(RANDOM_DATA)] // non-utf-8
 fn test_render_html_chrome_invalid(#[case] html_data: &[u8]) {
-    let result = render_html_chrome(ctx, html_data);
+    let result = render_html_chrome(&default_ctx(), html_data);
     assert!(result.is_err(), "HTML generation should fail");
 }
+
+#[rstest]
+#[case("file:///tmp/test.html", true)]
+#[case("data:text/html;base64,PGh0bWw+", true)]
+#[case("about:blank", true)]
+#[case("https://example.org", false)]
+#[case("http://localhost:8080", false)]
+fn test_is_local_url(#[case] url: &str, #[case] expected: bool) {
+    assert_eq!(is_local_url(url), expected);
+}
+
+#[test]
+fn test_launch_browser_unreachable_ws() {
+    let options = HtmlOptions {
+        ws_url: Some("ws://127.0.0.1:9/devtools/browser/kv".to_string()),
+        ..HtmlOptions::default()
+    };
+    let Err(err) = launch_browser(&options) else {
+        panic!("connecting to a closed port should fail");
+    };
+    assert_eq!(
+        err.to_string(),
+        "Failed to connect to browser at ws://127.0.0.1:9/devtools/browser/kv"
+    );
+    // closing without a browser, and more than once, is fine
+    drop(BrowserGuard);
+    close_browser();
+}
+
+#[test]
+fn test_offline_browser_blocks_websocket() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let html = format!(
+        "<html><body><script>new WebSocket('ws://127.0.0.1:{}/');</script></body></html>",
+        port
+    );
+
+    // a browser of its own, the shared one may have been launched online by another test
+    let options = HtmlOptions {
+        offline: true,
+        ..HtmlOptions::default()
+    };
+    let (browser, _profile_dir) = launch_browser(&options).unwrap();
+    let tab = browser.new_tab().unwrap();
+    let url = format!(
+        "data:text/html;base64,{}",
+        general_purpose::STANDARD.encode(html)
+    );
+    tab.navigate_to(&url).unwrap();
+    tab.wait_until_navigated().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    assert!(
+        listener.accept().is_err(),
+        "the page opened a WebSocket connection in offline mode"
+    );
+}
+
+/// Connects to a browser of its own like `--browser-ws` does, the shared one may have been
+/// launched with other options by another test. The launched browser must be kept alive.
+fn connect_test_browser(options: &HtmlOptions) -> (Browser, Browser, Option<TempDir>) {
+    let (launched, profile_dir) = launch_browser(&HtmlOptions::default()).unwrap();
+    let options = HtmlOptions {
+        ws_url: Some(launched.get_ws_url()),
+        ..options.clone()
+    };
+    let (connected, _) = launch_browser(&options).unwrap();
+    (connected, launched, profile_dir)
+}
+
+#[rstest]
+#[case(true, false)]
+#[case(false, true)]
+fn test_offline_blocks_remote_image(#[case] offline: bool, #[case] expected_request: bool) {
+    // a server on the loopback interface stands in for a remote host
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let dir = tempfile::tempdir().unwrap();
+    let page = dir.path().join("remote-image.html");
+    std::fs::write(
+        &page,
+        format!(
+            "<html><body><img src=\"http://127.0.0.1:{}/image.png\"></body></html>",
+            port
+        ),
+    )
+    .unwrap();
+
+    let options = HtmlOptions {
+        offline,
+        ..HtmlOptions::default()
+    };
+    let (browser, _launched, _profile_dir) = connect_test_browser(&options);
+    let tab = browser.new_tab().unwrap();
+    prepare_tab(&tab, &options, None).unwrap();
+    tab.navigate_to(&file_url(&page)).unwrap();
+    tab.wait_until_navigated().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    assert_eq!(listener.accept().is_ok(), expected_request);
+}
+
+#[rstest]
+fn test_javascript_over_websocket(#[values(true, false)] javascript: bool) {
+    let html = "<html><body><script>document.body.className = 'js' + 'ran';</script></body></html>";
+    let options = HtmlOptions {
+        javascript,
+        ..HtmlOptions::default()
+    };
+    let (browser, _launched, _profile_dir) = connect_test_browser(&options);
+    let tab = browser.new_tab().unwrap();
+    prepare_tab(&tab, &options, None).unwrap();
+    let url = format!(
+        "data:text/html;base64,{}",
+        general_purpose::STANDARD.encode(html)
+    );
+    tab.navigate_to(&url).unwrap();
+    tab.wait_until_navigated().unwrap();
+
+    assert_eq!(tab.get_content().unwrap().contains("jsran"), javascript);
+}
+
+#[rstest]
+#[case(MarkdownTheme::Light)]
+#[case(MarkdownTheme::Dark)]
+fn test_markdown_to_html(#[case] theme: MarkdownTheme) {
+    let markdown = "# Title\n\n![logo](logo.png)\n\n```rust\nfn main() {}\n```\n";
+    let html = markdown_to_html(markdown, theme, std::path::Path::new("/tmp/docs")).unwrap();
+
+    assert!(html.contains("<base href=\"file:///tmp/docs/\">"));
+    assert!(html.contains("<h1>Title</h1>"));
+    assert!(html.contains("<img src=\"logo.png\" alt=\"logo\" />"));
+    // highlighted code blocks are inlined with styles
+    assert!(html.contains("<pre style=\"background-color:"));
+    assert!(!html.contains("<code class=\"language-rust\">"));
+}
+
+#[rstest]
+#[case("pptx", true)]
+#[case("odp", true)]
+#[case("docx", false)]
+#[case("xlsx", false)]
+fn test_office_export_notes(#[case] extension: &str, #[case] valid: bool) {
+    let ctx = KvContext {
+        office: OfficeOptions {
+            notes: true,
+            ..OfficeOptions::default()
+        },
+        ..default_ctx()
+    };
+    let result = office_export(&ctx, b"", extension);
+    assert_eq!(result.is_ok(), valid);
+    if let Ok((filter, _)) = result {
+        assert!(filter.unwrap().contains(r#""ExportOnlyNotesPages":{"type":"boolean","value":"true"}"#));
+    }
+}
+
+#[rstest]
+#[case("/tmp/docs", "file:///tmp/docs")]
+#[case("/tmp/my docs/\"a&b\"", "file:///tmp/my%20docs/%22a%26b%22")]
+#[case("/tmp/<script>/ä", "file:///tmp/%3Cscript%3E/%C3%A4")]
+#[case("/tmp/kv 50%/#1/libreoffice", "file:///tmp/kv%2050%25/%231/libreoffice")]
+fn test_file_url(#[case] path: &str, #[case] expected: &str) {
+    assert_eq!(file_url(std::path::Path::new(path)), expected);
+}
+
+#[rstest]
+#[case("file:///tmp/kv%2050%25/%231/style.css", Some("/tmp/kv 50%/#1/style.css"))]
+#[case("file:///tmp/%3Cscript%3E/%C3%A4", Some("/tmp/<script>/ä"))]
+#[case("file://localhost/tmp/font.woff2?v=2#icons", Some("/tmp/font.woff2"))]
+#[case("file:///tmp/100%", Some("/tmp/100%"))]
+#[case("https://example.com/style.css", None)]
+fn test_file_url_path(#[case] url: &str, #[case] expected: Option<&str>) {
+    assert_eq!(file_url_path(url), expected.map(PathBuf::from));
+}
+
+#[test]
+fn test_with_loaded_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let ctx = KvContext {
+        cache_mode: CacheMode::Custom(dir.path().join("cache")),
+        ..default_ctx()
+    };
+    let style = dir.path().join("style.css");
+    std::fs::write(&style, "body {}").unwrap();
+    // stands in for a page loading a stylesheet through the request interception
+    let render = |files: Option<&[&[u8]]>, loaded: Option<&LoadedFiles>| {
+        loaded.unwrap().lock().unwrap().insert(style.clone());
+        Ok(files.map(|files| files.concat()))
+    };
+
+    // the files are unknown before the first render
+    let key = "a".repeat(64);
+    assert_eq!(with_loaded_files(&ctx, &key, render).unwrap(), None);
+    let content = with_loaded_files(&ctx, &key, render).unwrap().unwrap();
+
+    // an edited stylesheet changes the key
+    std::fs::write(&style, "body { color: red }").unwrap();
+    let changed = with_loaded_files(&ctx, &key, render).unwrap().unwrap();
+    assert_ne!(changed, content);
+
+    // without a cache nothing is recorded
+    let ctx = KvContext {
+        cache_mode: CacheMode::Disabled,
+        ..default_ctx()
+    };
+    let uncached = with_loaded_files(&ctx, &key, |files, loaded| {
+        Ok((files.is_none(), loaded.is_none()))
+    });
+    assert_eq!(uncached.unwrap(), (true, true));
+}
+
+#[rstest]
+#[case("{}", "/tmp/{width}", vec![false, false, true])]
+#[case("-g{width}x{width}", "-g800x800", vec![true, false, false])]
+#[case("{{}}:{}", "/tmp/output:/tmp/{width}", vec![false, true, true])]
+#[case("-r300", "-r300", vec![false, false, false])]
+fn test_replace_placeholders(
+    #[case] arg: &str,
+    #[case] expected: &str,
+    #[case] expected_found: Vec<bool>,
+) {
+    // sorted by descending length, substituted values are never replaced again
+    let placeholders = vec![
+        ("{width}", "800".to_string()),
+        ("{{}}", "/tmp/output".to_string()),
+        ("{}", "/tmp/{width}".to_string()),
+    ];
+    let mut found = vec![false; placeholders.len()];
+    assert_eq!(
+        replace_placeholders(arg, &placeholders, &mut found),
+        expected
+    );
+    assert_eq!(found, expected_found);
+}
+
+#[test]
+fn test_split_png_stream() {
+    let stream = [PNG_DATA, PNG_DATA, PNG_DATA].concat();
+    assert_eq!(split_png_stream(&stream), vec![PNG_DATA; 3]);
+    assert_eq!(split_png_stream(PNG_DATA), vec![PNG_DATA]);
+
+    // truncated or other data is kept as is
+    let truncated = &stream[..stream.len() - 4];
+    assert_eq!(split_png_stream(truncated), vec![truncated]);
+    assert_eq!(split_png_stream(RANDOM_DATA), vec![RANDOM_DATA]);
+}
+
+#[test]
+fn test_parse_ansi() {
+    let lines = parse_ansi("\x1b[1;38;2;249;38;114mfn\x1b[0m main\tx\n\x1b[38;5;9m}\x1b[0m\n");
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0][0].text, "fn");
+    assert_eq!(lines[0][0].style.foreground.as_deref(), Some("#f92672"));
+    assert!(lines[0][0].style.bold);
+    assert_eq!(lines[0][1].text, " main x");
+    assert_eq!(lines[0][1].style, TextStyle::default());
+    assert_eq!(lines[1][0].style.foreground.as_deref(), Some("#ff0000"));
+}
+
+#[test]
+fn test_text_to_svg() {
+    let svg = text_to_svg("a < b\n\x1b[48;5;236m  \x1b[0m\n", "monospace", "#272822");
+    assert!(svg.contains(r#"<text x="16" y="#));
+    assert!(svg.contains("a &lt; b"));
+    assert!(svg.contains(r##"fill="#303030""##));
+    assert!(usvg::Tree::from_str(&svg, &usvg::Options::default()).is_ok());
+}
+
+#[rstest]
+#[case(0, None, (2, 1), [RED, BLUE])]
+#[case(90, None, (1, 2), [RED, BLUE])]
+#[case(180, None, (2, 1), [BLUE, RED])]
+#[case(270, None, (1, 2), [BLUE, RED])]
+#[case(0, Some(Flip::Horizontal), (2, 1), [BLUE, RED])]
+#[case(0, Some(Flip::Vertical), (2, 1), [RED, BLUE])]
+#[case(90, Some(Flip::Vertical), (1, 2), [BLUE, RED])]
+fn test_transform_image(
+    #[case] rotate: u16,
+    #[case] flip: Option<Flip>,
+    #[case] expected_size: (u32, u32),
+    #[case] expected_pixels: [Rgba<u8>; 2],
+) {
+    // red on the left, blue on the right
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| [RED, BLUE][x as usize]));
+    let options = ImageOptions {
+        rotate,
+        flip,
+        ..ImageOptions::default()
+    };
+
+    let img = transform_image(img, &options);
+    assert_eq!(img.dimensions(), expected_size);
+    let (last_x, last_y) = (expected_size.0 - 1, expected_size.1 - 1);
+    assert_eq!(img.get_pixel(0, 0), expected_pixels[0]);
+    assert_eq!(img.get_pixel(last_x, last_y), expected_pixels[1]);
+}
+
+/// Renders an input upright and rotated by 90 degrees, the sides of the result must be swapped.
+fn assert_rotated(render: impl Fn(&KvContext) -> Result<DynamicImage>) {
+    let ctx = KvContext {
+        resize_mode: ResizeMode::Manual {
+            width: Some(120),
+            height: Some(120),
+        },
+        ..default_ctx()
+    };
+    let rotated_ctx = KvContext {
+        image: ImageOptions {
+            rotate: 90,
+            ..ImageOptions::default()
+        },
+        ..ctx.clone()
+    };
+    let upright = render(&ctx).unwrap();
+    let rotated = render(&rotated_ctx).unwrap();
+    assert_eq!(rotated.dimensions(), (upright.height(), upright.width()));
+}
+
+#[test]
+fn test_render_svg_rotated() {
+    let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="2" height="1">
+        <rect width="1" height="1" fill="red"/><rect x="1" width="1" height="1" fill="blue"/>
+        </svg>"#;
+    assert_rotated(|ctx| rasterize_svg(ctx, svg));
+}
+
+#[test]
+fn test_render_pdf_rotated() {
+    assert_rotated(|ctx| rasterize_pdf(ctx, PDF_DATA));
+}
+
+#[test]
+fn test_render_html_rotated() {
+    assert_rotated(|ctx| capture_html(ctx, HTML_DATA, None));
+}
+
+/// Inserts an EXIF segment with the given orientation after the start of a JPEG image.
+fn with_exif_orientation(jpeg: &[u8], orientation: u8) -> Vec<u8> {
+    let mut exif = b"\xff\xe1\x00\x22Exif\x00\x00MM\x00\x2a\x00\x00\x00\x08".to_vec();
+    // a single IFD entry: tag 0x0112 (orientation), type short, count 1
+    exif.extend_from_slice(b"\x00\x01\x01\x12\x00\x03\x00\x00\x00\x01");
+    exif.extend_from_slice(&[0, orientation, 0, 0, 0, 0, 0, 0]);
+    [&jpeg[..2], &exif, &jpeg[2..]].concat()
+}
+
+#[rstest]
+#[case(true, (1, 2))]
+#[case(false, (2, 1))]
+fn test_decode_image_orientation(#[case] orient: bool, #[case] expected_size: (u32, u32)) {
+    let img = DynamicImage::ImageRgb8(image::RgbImage::new(2, 1));
+    let mut jpeg = Vec::new();
+    img.write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+        .unwrap();
+    // rotated by 90 degrees
+    let jpeg = with_exif_orientation(&jpeg, 6);
+
+    let decoded = decode_image(&jpeg, orient).unwrap();
+    assert_eq!(decoded.dimensions(), expected_size);
+}
+
+const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+/// Builds the markers of a JPEG image up to its frame header.
+fn jpeg_header(components: u8, adobe: bool) -> Vec<u8> {
+    let mut jpeg = b"\xff\xd8\xff\xe0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00".to_vec();
+    if adobe {
+        jpeg.extend_from_slice(b"\xff\xee\x00\x0eAdobe\x00\x64\x00\x00\x00\x00\x02");
+    }
+    // baseline frame of 3x2 pixels
+    jpeg.extend_from_slice(b"\xff\xc0\x00\x14\x08\x00\x02\x00\x03");
+    jpeg.push(components);
+    jpeg.extend_from_slice(&[0; 12]);
+    jpeg
+}
+
+#[rstest]
+#[case(4, false, Some(CmykJpeg { width: 3, height: 2, adobe: false, ycck: false }))]
+#[case(4, true, Some(CmykJpeg { width: 3, height: 2, adobe: true, ycck: true }))]
+#[case(3, true, None)]
+fn test_cmyk_jpeg_header(
+    #[case] components: u8,
+    #[case] adobe: bool,
+    #[case] expected: Option<CmykJpeg>,
+) {
+    assert_eq!(cmyk_jpeg_header(&jpeg_header(components, adobe)), expected);
+    assert_eq!(cmyk_jpeg_header(PNG_DATA), None);
+}
+
+#[test]
+fn test_cmyk_to_rgb() {
+    let cmyk = [0, 0, 0, 0, 255, 0, 0, 0, 0, 0, 0, 255, 0, 255, 255, 0];
+    let rgb = [255, 255, 255, 0, 255, 255, 0, 0, 0, 255, 0, 0];
+    assert_eq!(cmyk_to_rgb(&cmyk), rgb);
+}
+
+#[rstest]
+#[case(CMYK_JPEG)]
+#[case(YCCK_JPEG)]
+fn test_decode_cmyk_jpeg(#[case] data: &[u8]) {
+    // both store red inverted, as written by Adobe applications
+    let img = decode_image(data, true).unwrap();
+    assert_eq!(img.dimensions(), (8, 8));
+    assert_color_near(img.get_pixel(4, 4), [255, 0, 0]);
+}
+
+#[test]
+fn test_convert_to_srgb_display_p3() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([200, 100, 50, 255])));
+    let converted = convert_to_srgb(img, DISPLAY_P3_ICC);
+    // the same color is more saturated in the smaller sRGB gamut
+    assert_color_near(converted.get_pixel(1, 1), [215, 93, 31]);
+    assert_eq!(converted.get_pixel(1, 1)[3], 255);
+}
+
+#[test]
+fn test_convert_to_srgb_16bit() {
+    let color = image::Rgba([200 * 257, 100 * 257, 50 * 257, u16::MAX]);
+    let img = DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(2, 2, color));
+    let converted = convert_to_srgb(img, DISPLAY_P3_ICC);
+
+    // the precision of 16-bit channels is kept
+    let DynamicImage::ImageRgba16(buffer) = &converted else {
+        panic!("expected a 16-bit image, got {:?}", converted.color());
+    };
+    let pixel = buffer.get_pixel(1, 1);
+    assert!(pixel.0[..3].iter().any(|value| value % 257 != 0));
+    assert_color_near(converted.get_pixel(1, 1), [215, 93, 31]);
+}
+
+#[test]
+fn test_is_srgb() {
+    assert!(is_srgb(&ColorProfile::new_srgb()));
+    assert!(!is_srgb(&ColorProfile::new_display_p3()));
+    assert!(!is_srgb(&ColorProfile::new_adobe_rgb()));
+
+    // sRGB profiles store their curves as tables as well
+    let table = (0..1024)
+        .map(|i| {
+            let value = i as f64 / 1023.0;
+            let linear = if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            };
+            (linear * 65535.0).round() as u16
+        })
+        .collect::<Vec<_>>();
+    let mut profile = ColorProfile::new_srgb();
+    profile.red_trc = Some(ToneReprCurve::Lut(table.clone()));
+    profile.green_trc = Some(ToneReprCurve::Lut(table.clone()));
+    profile.blue_trc = Some(ToneReprCurve::Lut(table));
+    assert!(is_srgb(&profile));
+
+    // a gamma of 2.2 is not sRGB
+    profile.green_trc = Some(curve_from_gamma(2.2));
+    assert!(!is_srgb(&profile));
+}
+
+#[test]
+fn test_convert_to_srgb_srgb_profile() {
+    let profile = ColorProfile::new_srgb().encode().unwrap();
+    let color = image::Rgba([200 * 257 + 1, 100 * 257, 50 * 257, u16::MAX]);
+    let img = DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(2, 2, color));
+    assert_eq!(convert_to_srgb(img.clone(), &profile), img);
+}
+
+fn assert_color_near(pixel: Rgba<u8>, expected: [u8; 3]) {
+    let close = (0..3).all(|i| pixel[i].abs_diff(expected[i]) <= 3);
+    assert!(close, "{:?} is not close to {:?}", pixel, expected);
+}
+
+#[test]
+fn test_convert_to_srgb_invalid_profile() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, RED));
+    assert_eq!(convert_to_srgb(img.clone(), b"not a profile"), img);
+}
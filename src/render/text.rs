@@ -0,0 +1,334 @@
+use anyhow::Result;
+use image::DynamicImage;
+use std::fmt::Write;
+use usvg::fontdb::Database;
+
+use super::rasterize_svg_with_fonts;
+use crate::{KvContext, cached_render};
+
+/// Font size of rendered text in pixels.
+const FONT_SIZE: f32 = 16.0;
+/// Advance of a monospaced character relative to the font size.
+const CHAR_WIDTH: f32 = 0.6;
+const LINE_HEIGHT: f32 = 1.4;
+const PADDING: f32 = 16.0;
+const TAB_WIDTH: usize = 4;
+
+// colors of the default bat theme (Monokai Extended)
+const DEFAULT_BACKGROUND: &str = "#272822";
+const DEFAULT_FOREGROUND: &str = "#f8f8f2";
+
+/// The 16 standard colors of xterm.
+const ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// Style of a text span, colors are hex strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+fn ansi_256_color(n: u16) -> Option<String> {
+    let n = u8::try_from(n).ok()?;
+    Some(match n {
+        0..16 => ANSI_COLORS[n as usize].to_string(),
+        16..232 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                level(n / 36),
+                level(n / 6 % 6),
+                level(n % 6)
+            )
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    })
+}
+
+/// Applies the parameters of a "select graphic rendition" escape sequence.
+fn apply_sgr(style: &mut TextStyle, params: &str) {
+    // an empty parameter is a reset
+    let codes: Vec<u16> = params
+        .split(';')
+        .map(|code| code.parse().unwrap_or(0))
+        .collect();
+
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 => *style = TextStyle::default(),
+            1 => style.bold = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => style.bold = false,
+            23 => style.italic = false,
+            24 => style.underline = false,
+            code @ 30..=37 => style.foreground = Some(ANSI_COLORS[code as usize - 30].to_string()),
+            code @ 90..=97 => style.foreground = Some(ANSI_COLORS[code as usize - 82].to_string()),
+            39 => style.foreground = None,
+            code @ 40..=47 => style.background = Some(ANSI_COLORS[code as usize - 40].to_string()),
+            code @ 100..=107 => {
+                style.background = Some(ANSI_COLORS[code as usize - 92].to_string())
+            }
+            49 => style.background = None,
+            code @ (38 | 48) => {
+                let color = match codes.get(i + 1) {
+                    Some(5) => {
+                        i += 2;
+                        codes.get(i).and_then(|&n| ansi_256_color(n))
+                    }
+                    Some(2) => {
+                        i += 4;
+                        codes.get(i - 2..=i).map(|rgb| {
+                            let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|v| v.min(255));
+                            format!("#{:02x}{:02x}{:02x}", r, g, b)
+                        })
+                    }
+                    _ => None,
+                };
+                if code == 38 {
+                    style.foreground = color;
+                } else {
+                    style.background = color;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+fn push_span(line: &mut Vec<TextSpan>, text: &mut String, style: &TextStyle) {
+    if !text.is_empty() {
+        line.push(TextSpan {
+            text: std::mem::take(text),
+            style: style.clone(),
+        });
+    }
+}
+
+/// Splits text with ANSI escape sequences into lines of styled spans. Tabs are expanded,
+/// other escape sequences and control characters are dropped.
+pub fn parse_ansi(text: &str) -> Vec<Vec<TextSpan>> {
+    let mut lines = vec![Vec::new()];
+    let mut style = TextStyle::default();
+    let mut current = String::new();
+    let mut column = 0;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let line = lines.last_mut().expect("lines are never empty");
+        match c {
+            '\x1b' => {
+                push_span(line, &mut current, &style);
+                match chars.next() {
+                    // control sequence, parameters up to the final byte
+                    Some('[') => {
+                        let mut params = String::new();
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                if c == 'm' {
+                                    apply_sgr(&mut style, &params);
+                                }
+                                break;
+                            }
+                            params.push(c);
+                        }
+                    }
+                    // operating system command (e.g. hyperlinks) up to BEL or ST
+                    Some(']') => {
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1b' {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            '\n' => {
+                push_span(line, &mut current, &style);
+                lines.push(Vec::new());
+                column = 0;
+            }
+            '\t' => {
+                let spaces = TAB_WIDTH - column % TAB_WIDTH;
+                current.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            c if c.is_control() => {}
+            c => {
+                current.push(c);
+                column += 1;
+            }
+        }
+    }
+    push_span(
+        lines.last_mut().expect("lines are never empty"),
+        &mut current,
+        &style,
+    );
+
+    // the final newline does not start another line
+    if lines.len() > 1 && lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Lays out text with ANSI colors as SVG, every character in its own monospaced cell.
+pub fn text_to_svg(text: &str, font: &str, background: &str) -> String {
+    let lines = parse_ansi(text);
+    let char_width = FONT_SIZE * CHAR_WIDTH;
+    let line_height = FONT_SIZE * LINE_HEIGHT;
+    let columns = lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|span| span.text.chars().count())
+                .sum::<usize>()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let width = (columns as f32 * char_width + 2.0 * PADDING).ceil();
+    let height = (lines.len() as f32 * line_height + 2.0 * PADDING).ceil();
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = write!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        escape_xml(background)
+    );
+    let _ = write!(
+        svg,
+        r#"<g font-family="{}" font-size="{FONT_SIZE}" fill="{DEFAULT_FOREGROUND}" xml:space="preserve">"#,
+        escape_xml(font)
+    );
+
+    for (row, line) in lines.iter().enumerate() {
+        let y = PADDING + row as f32 * line_height;
+        // centers the glyphs of the font size in the line
+        let baseline = y + (line_height + FONT_SIZE * 0.7) / 2.0;
+        let mut column = 0;
+        for span in line {
+            let len = span.text.chars().count();
+            let x = PADDING + column as f32 * char_width;
+            if let Some(background) = &span.style.background {
+                let _ = write!(
+                    svg,
+                    r#"<rect x="{x}" y="{y}" width="{}" height="{line_height}" fill="{background}"/>"#,
+                    len as f32 * char_width
+                );
+            }
+            if !span.text.trim().is_empty() {
+                let mut attributes = String::new();
+                if let Some(foreground) = &span.style.foreground {
+                    let _ = write!(attributes, r#" fill="{}""#, foreground);
+                }
+                if span.style.bold {
+                    attributes.push_str(r#" font-weight="bold""#);
+                }
+                if span.style.italic {
+                    attributes.push_str(r#" font-style="italic""#);
+                }
+                if span.style.underline {
+                    attributes.push_str(r#" text-decoration="underline""#);
+                }
+                let _ = write!(
+                    svg,
+                    r#"<text x="{x}" y="{baseline}"{attributes}>{}</text>"#,
+                    escape_xml(&span.text)
+                );
+            }
+            column += len;
+        }
+    }
+
+    svg.push_str("</g></svg>");
+    svg
+}
+
+fn has_font_family(fontdb: &Database, font: &str) -> bool {
+    fontdb.faces().any(|face| {
+        face.families
+            .iter()
+            .any(|(family, _)| family.eq_ignore_ascii_case(font))
+    })
+}
+
+/// Checks if a font family is installed on the system.
+pub fn is_font_installed(font: &str) -> bool {
+    let mut fontdb = Database::new();
+    fontdb.load_system_fonts();
+    has_font_family(&fontdb, font)
+}
+
+/// Renders text with ANSI colors (e.g. highlighted by bat) as image in a monospaced font,
+/// the system monospace font if `font` is not set. The background is the background color
+/// of the context or the one of the default theme.
+pub fn render_text(ctx: &KvContext, text: &str, font: Option<&str>) -> Result<DynamicImage> {
+    let background = ctx
+        .background_color
+        .map_or(DEFAULT_BACKGROUND.to_string(), |c| {
+            format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+        });
+    let svg = text_to_svg(text, font.unwrap_or("monospace"), &background);
+
+    cached_render(ctx, "text", &[svg.as_bytes()], || {
+        let mut fontdb = Database::new();
+        fontdb.load_system_fonts();
+
+        match font {
+            Some(font) => {
+                if !has_font_family(&fontdb, font) {
+                    anyhow::bail!("Font not found: {}", font);
+                }
+            }
+            None => {
+                // the generic monospace family defaults to a font that is rarely installed
+                let family = fontdb
+                    .faces()
+                    .find(|face| face.monospaced)
+                    .and_then(|face| face.families.first())
+                    .map(|(family, _)| family.clone());
+                if let Some(family) = family {
+                    fontdb.set_monospace_family(family);
+                }
+            }
+        }
+
+        rasterize_svg_with_fonts(ctx, svg.as_bytes(), fontdb)
+    })
+}
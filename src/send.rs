@@ -1,11 +1,13 @@
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
-use bat::{Input, PrettyPrinter};
+use bat::line_range::{LineRange, LineRanges};
+use bat::{Input, PrettyPrinter, WrappingMode};
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use image::codecs::png::PngEncoder;
 use image::{DynamicImage, GenericImageView, ImageEncoder};
-use std::io::{Cursor, Write};
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
 use std::path::PathBuf;
 
 const KITTY_CHUNK_SIZE: usize = 4096;
@@ -18,9 +20,74 @@ pub enum Mode {
     Raw,
 }
 
-pub enum PrinterInput {
+pub enum PrinterInput<'a> {
     File(PathBuf),
     Data(Vec<u8>),
+    /// Streamed to the output while it is read, e.g. large piped text.
+    Reader(Box<dyn Read + 'a>),
+}
+
+/// Options of the text printer, named after the options of `bat`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextOptions {
+    pub language: Option<String>,
+    pub theme: Option<String>,
+    pub line_numbers: bool,
+    pub grid: bool,
+    pub header: bool,
+    /// Inclusive 1-indexed line ranges to print, all lines if empty.
+    pub line_ranges: Vec<(usize, usize)>,
+    /// Inclusive 1-indexed line ranges to highlight.
+    pub highlight_lines: Vec<(usize, usize)>,
+    pub tab_width: Option<usize>,
+    /// Wrap long lines at the terminal width.
+    pub wrap: bool,
+    /// Terminal width in columns, used for wrapping and decorations.
+    pub term_width: Option<usize>,
+    /// Add a newline after the text if it is missing.
+    pub newline: bool,
+}
+
+/// Parses a line range like bat: "N", "N:M", ":M", "N:" or "N:+K".
+pub fn parse_line_range(range: &str) -> Result<(usize, usize)> {
+    let parse = |line: &str| {
+        line.trim()
+            .parse::<usize>()
+            .with_context(|| format!("Invalid line number: {:?}", line))
+    };
+    let (start, end) = match range.split_once(':') {
+        None => (parse(range)?, parse(range)?),
+        Some(("", "")) => anyhow::bail!("Invalid line range: {:?}", range),
+        Some(("", end)) => (1, parse(end)?),
+        Some((start, "")) => (parse(start)?, usize::MAX),
+        Some((start, end)) => {
+            let start = parse(start)?;
+            match end.strip_prefix('+') {
+                Some(count) => (start, start.saturating_add(parse(count)?)),
+                None => (start, parse(end)?),
+            }
+        }
+    };
+    if start == 0 || start > end {
+        anyhow::bail!("Invalid line range: {:?}", range);
+    }
+    Ok((start, end))
+}
+
+/// Returns the names of all syntax highlighting themes.
+pub fn list_themes() -> Vec<String> {
+    PrettyPrinter::new()
+        .themes()
+        .map(|theme| theme.to_string())
+        .collect()
+}
+
+/// Returns the names of all languages with their file extensions.
+pub fn list_languages() -> Vec<(String, Vec<String>)> {
+    PrettyPrinter::new()
+        .syntaxes()
+        .map(|syntax| (syntax.name, syntax.file_extensions))
+        .collect()
 }
 
 pub fn send_image(
@@ -106,11 +173,28 @@ pub fn send_image(
     Ok(())
 }
 
-pub fn pretty_print(
-    writer: &mut dyn Write,
+/// Forwards the output of bat to a writer while it is printed.
+struct StreamWriter<'w> {
+    writer: &'w mut dyn Write,
+    error: Option<io::Error>,
+    last: Option<char>,
+}
+
+impl fmt::Write for StreamWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.last = s.chars().last().or(self.last);
+        self.writer.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Highlights text with the options of bat and writes it with ANSI colors to `output`.
+fn print_text(
     input: PrinterInput,
-    language: Option<&str>,
-    newline: bool,
+    options: &TextOptions,
+    output: &mut dyn fmt::Write,
 ) -> Result<()> {
     let mut printer = PrettyPrinter::new();
 
@@ -122,23 +206,80 @@ pub fn pretty_print(
             // requires a reader
             printer.input(Input::from_reader(Box::new(Cursor::new(data))));
         }
+        PrinterInput::Reader(reader) => {
+            printer.input(Input::from_reader(reader));
+        }
     };
 
-    if let Some(lang) = language {
+    if let Some(lang) = &options.language {
         printer.language(lang);
     }
+    if let Some(theme) = &options.theme {
+        printer.theme(theme);
+    }
+    if let Some(term_width) = options.term_width {
+        printer.term_width(term_width);
+    }
+    if !options.line_ranges.is_empty() {
+        let ranges = options
+            .line_ranges
+            .iter()
+            .map(|&(start, end)| LineRange::new(start, end))
+            .collect();
+        printer.line_ranges(LineRanges::from(ranges));
+    }
+    for &(start, end) in &options.highlight_lines {
+        printer.highlight_range(start, end);
+    }
+    printer
+        .line_numbers(options.line_numbers)
+        .grid(options.grid)
+        .header(options.header)
+        .tab_width(options.tab_width)
+        .wrapping_mode(if options.wrap {
+            WrappingMode::Character
+        } else {
+            WrappingMode::NoWrapping(false)
+        });
 
-    // capture output to handle the optional newline
-    let mut output_str = String::new();
     printer
-        .print_with_writer(Some(&mut output_str))
+        .print_with_writer(Some(output))
         .map_err(|e| anyhow::anyhow!("Syntax highlighting failed: {}", e))?;
+    Ok(())
+}
+
+/// Highlights text with the options of bat and returns it with ANSI colors.
+pub fn highlight_text(input: PrinterInput, options: &TextOptions) -> Result<String> {
+    let mut output_str = String::new();
+    print_text(input, options, &mut output_str)?;
 
-    if newline && !output_str.ends_with('\n') {
+    if options.newline && !output_str.ends_with('\n') {
         output_str.push('\n');
     }
+    Ok(output_str)
+}
+
+/// Highlights text and streams it to the writer, large inputs are never held in memory.
+pub fn pretty_print(
+    writer: &mut dyn Write,
+    input: PrinterInput,
+    options: &TextOptions,
+) -> Result<()> {
+    let mut output = StreamWriter {
+        writer: &mut *writer,
+        error: None,
+        last: None,
+    };
+    let result = print_text(input, options, &mut output);
+    let StreamWriter { error, last, .. } = output;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    result?;
 
-    writer.write_all(output_str.as_bytes())?;
+    if options.newline && last != Some('\n') {
+        writer.write_all(b"\n")?;
+    }
     writer.flush()?;
     Ok(())
 }
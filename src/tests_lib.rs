@@ -1,25 +1,16 @@
 // unit tests
 use super::*;
 use image::Rgba;
-use image::{DynamicImage, GenericImageView};
 use rstest::rstest;
 use std::path::PathBuf;
 
-const PNG_DATA: &[u8] = include_bytes!("../fixtures/test.png");
-const SVG_DATA: &[u8] = include_bytes!("../fixtures/test.svg");
+const PNG_DATA: &[u8] = include_bytes!("../tests/fixtures/test.png");
+const SVG_DATA: &[u8] = include_bytes!("../tests/fixtures/test.svg");
 
 fn default_ctx() -> KvContext {
-    let resize_fn = |img: &DynamicImage| -> (u32, u32) { img.dimensions() };
     KvContext {
-        input_type: InputType::Auto,
-        resize_fn: Box::new(resize_fn),
-        conf_w: None,
-        conf_h: None,
-        term_width: 100,
-        term_height: 50,
-        page_indices: None,
-        use_cache: false,
-        cache_dir: None,
+        term_size: (100, 50),
+        ..KvContext::default()
     }
 }
 // get_term_size
@@ -47,51 +38,21 @@ fn test_parse_color_invalid(#[case] color: &str) {
 }
 
 #[rstest]
-#[case(100, 50, Some(50), None, false, false, false, false, 50, 25)] // explicit width
-#[case(100, 50, None, Some(25), false, false, false, false, 50, 25)] // explicit height
-#[case(1000, 500, None, None, false, false, false, false, 100, 50)] // auto-downscale
-#[case(50, 50, None, None, true, false, false, false, 100, 100)] // fullwidth
-#[case(200, 25, None, None, false, true, false, false, 400, 50)] // fullheight
-#[case(500, 500, None, None, false, false, true, false, 50, 50)] // resize (bound by height)
-#[case(1000, 200, None, None, false, false, true, false, 100, 20)] // resize (bound by width)
-#[case(1000, 500, None, None, false, false, false, true, 1000, 500)] // noresize
+#[case((100, 50), ResizeMode::Manual { width: Some(50), height: None }, (50, 25))]
+#[case((100, 50), ResizeMode::Manual { width: None, height: Some(25) }, (50, 25))]
+#[case((1000, 500), ResizeMode::ClipTerminal, (100, 50))] // auto-downscale
+#[case((50, 50), ResizeMode::ClipTerminal, (50, 50))]
+#[case((50, 50), ResizeMode::FitWidth, (100, 100))]
+#[case((200, 25), ResizeMode::FitHeight, (400, 50))]
+#[case((500, 500), ResizeMode::FitTerminal, (50, 50))] // bound by height
+#[case((1000, 200), ResizeMode::FitTerminal, (100, 20))] // bound by width
+#[case((1000, 500), ResizeMode::Original, (1000, 500))]
 fn test_calculate_dimensions(
-    #[case] img_w: u32,
-    #[case] img_h: u32,
-    #[case] conf_w: Option<u32>,
-    #[case] conf_h: Option<u32>,
-    #[case] fullwidth: bool,
-    #[case] fullheight: bool,
-    #[case] resize: bool,
-    #[case] noresize: bool,
-    #[case] expected_w: u32,
-    #[case] expected_h: u32,
+    #[case] img_dims: (u32, u32),
+    #[case] mode: ResizeMode,
+    #[case] expected: (u32, u32),
 ) {
-    let img_dims = (img_w, img_h);
-    let term_width = 100; // fixed
-    let term_height = 50; // fixed
-
-    // quick sanity check for exclusive options
-    assert!(!(fullwidth && fullheight));
-    assert!(!(resize && noresize));
-    assert!(!((resize || noresize) && (fullwidth || fullheight)));
-    assert!(
-        !((conf_w.is_some() || conf_h.is_some())
-            && (fullwidth || fullheight || resize || noresize))
-    );
-
-    let (w, h) = calculate_dimensions(
-        img_dims,
-        (conf_w, conf_h),
-        fullwidth,
-        fullheight,
-        resize,
-        noresize,
-        (term_width, term_height),
-    );
-
-    assert_eq!(w, expected_w);
-    assert_eq!(h, expected_h);
+    assert_eq!(calculate_dimensions(img_dims, mode, (100, 50)), expected);
 }
 
 #[rstest]
@@ -127,9 +88,9 @@ fn test_parse_pages_invalid(#[case] input: &str) {
 }
 
 #[rstest]
-#[case(PathBuf::from("fixtures/test.svg"), InputType::Svg)]
-#[case(PathBuf::from("fixtures/test.png"), InputType::Image)]
-#[case(PathBuf::from("fixtures/test.pdf"), InputType::Pdf)]
+#[case(PathBuf::from("tests/fixtures/test.svg"), InputType::Svg)]
+#[case(PathBuf::from("tests/fixtures/test.png"), InputType::Image)]
+#[case(PathBuf::from("tests/fixtures/test.pdf"), InputType::Pdf)]
 fn test_load_file(#[case] path: PathBuf, #[case] input_type: InputType) {
     let mut ctx = default_ctx();
     ctx.input_type = input_type;
@@ -140,16 +101,15 @@ fn test_load_file(#[case] path: PathBuf, #[case] input_type: InputType) {
 }
 
 #[rstest]
-#[case(PathBuf::from("nonexistent"), InputType::Auto, "Failed to open file")]
 #[case(
-    PathBuf::from("fixtures/test.random"),
+    PathBuf::from("nonexistent"),
     InputType::Auto,
-    "Failed to decode input: The image format could not be determined"
+    "Failed to open file: nonexistent"
 )]
 #[case(
-    PathBuf::from("fixtures/test.svg"),
+    PathBuf::from("tests/fixtures/test.svg"),
     InputType::Image,
-    "Failed to load image"
+    "Failed to decode image data"
 )]
 fn test_load_file_invalid(
     #[case] path: PathBuf,
@@ -165,12 +125,12 @@ fn test_load_file_invalid(
 
 #[rstest]
 #[case(
-    PathBuf::from("fixtures/test.png"),
+    PathBuf::from("tests/fixtures/test.png"),
     InputType::Svg,
     "Failed to parse SVG"
 )]
 #[case(
-    PathBuf::from("fixtures/test.pdf"),
+    PathBuf::from("tests/fixtures/test.pdf"),
     InputType::Svg,
     "Failed to parse SVG"
 )]
@@ -187,7 +147,7 @@ fn test_load_file_invalid_svg(
 }
 
 #[rstest]
-#[case("fixtures/test.png".as_bytes())]
+#[case("tests/fixtures/test.png".as_bytes())]
 #[case(PNG_DATA)]
 fn test_load_data(#[case] data: &[u8]) {
     let ctx = default_ctx();
@@ -196,7 +156,7 @@ fn test_load_data(#[case] data: &[u8]) {
 }
 
 #[rstest]
-#[case("fixtures/test.svg".as_bytes())]
+#[case("tests/fixtures/test.svg".as_bytes())]
 #[case(SVG_DATA)]
 fn test_load_data_svg(#[case] data: &[u8]) {
     let ctx = default_ctx();
@@ -205,20 +165,106 @@ fn test_load_data_svg(#[case] data: &[u8]) {
 }
 
 #[rstest]
-#[case("nonexistent".as_bytes(), Some("Failed to decode input: The image format could not be determined"))]
 #[case(
-    b"invalidbinary\x99\x98\x97\x96",
-    Some("Failed to decode input: The image format could not be determined")
+    b"\x89PNG\r\n\x1a\n\x00",
+    "Failed to decode input: Failed to decode image data"
 )]
 #[case(
-    b"",
-    Some("Failed to decode input: The image format could not be determined")
+    b"GIF89a\xff\xff",
+    "Failed to decode input: Failed to decode image data"
 )]
-fn test_load_data_invalid(#[case] data: &[u8], #[case] err_msg: Option<&str>) {
+fn test_load_data_invalid(#[case] data: &[u8], #[case] err_msg: &str) {
     let ctx = default_ctx();
     let result = load_data(&ctx, data, "");
-    // assert!(result.is_err());
-    assert_eq!(result.unwrap_err().to_string(), err_msg.unwrap());
+    assert_eq!(result.unwrap_err().to_string(), err_msg);
+}
+
+#[rstest]
+#[case(b"nonexistent")]
+#[case(b"")]
+fn test_load_data_text(#[case] data: &[u8]) {
+    // neither a path nor a known format, shown as text instead of failing to decode
+    let ctx = default_ctx();
+    let result = load_data(&ctx, data, "");
+    assert!(matches!(result, Ok(LoadResult::Data(text)) if text == data));
+}
+
+#[rstest]
+#[case(PathBuf::from("tests/fixtures/test.random"), InputType::Auto)]
+#[case(PathBuf::from("tests/fixtures/test.png"), InputType::Hex)]
+fn test_load_file_hex(#[case] path: PathBuf, #[case] input_type: InputType) {
+    let mut ctx = default_ctx();
+    ctx.input_type = input_type;
+    let result = load_file(&ctx, &path);
+    assert!(matches!(result, Ok(LoadResult::Dump(_))));
+}
+
+#[test]
+fn test_load_data_broken_image_not_dumped() {
+    let ctx = default_ctx();
+    // a PNG signature followed by garbage
+    let result = load_data(&ctx, b"\x89PNG\r\n\x1a\n\x99\x98\x97\x96", "");
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .starts_with("Failed to decode input")
+    );
+}
+
+#[rstest]
+#[case(b"\x7fELF\x02\x01\x01\x00\xff\xfe")]
+#[case(b"\x1f\x8b\x08\x00\xff\xfe\xfd\xfc")] // gzip
+fn test_load_data_known_binary_dumped(#[case] data: &[u8]) {
+    let ctx = default_ctx();
+    let result = load_data(&ctx, data, "");
+    assert!(matches!(result, Ok(LoadResult::Dump(_))));
+}
+
+#[rstest]
+#[case(InputType::Auto, Ok(b"%PDF-1.7 document".as_slice()))]
+#[case(
+    InputType::Image,
+    Err("PDF output is only supported for HTML, markdown, PDF and office inputs")
+)]
+fn test_load_pdf_data(#[case] input_type: InputType, #[case] expected: Result<&[u8], &str>) {
+    // all pages are copied as is
+    let ctx = KvContext {
+        input_type,
+        page_indices: None,
+        ..default_ctx()
+    };
+    let result = load_pdf_data(&ctx, b"%PDF-1.7 document", "");
+    assert_eq!(
+        result.as_deref().map_err(|e| e.to_string()),
+        expected.map_err(String::from)
+    );
+}
+
+#[test]
+fn test_load_data_hex_capped() {
+    let mut ctx = default_ctx();
+    ctx.input_type = InputType::Hex;
+    let data = vec![0x99; DUMP_SIZE + 100];
+    let Ok(LoadResult::Dump(dump)) = load_data(&ctx, &data, "") else {
+        panic!("expected a hex dump");
+    };
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.ends_with("00010000\n... 100 more bytes, select them with --bytes\n"));
+}
+
+#[test]
+fn test_load_data_hex_range() {
+    let mut ctx = default_ctx();
+    ctx.byte_range = Some((16, 18));
+    let Ok(LoadResult::Dump(dump)) =
+        load_data(&ctx, b"invalidbinary\x99\x98\x97\x96\x95\x94\x93", "")
+    else {
+        panic!("expected a hex dump");
+    };
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.starts_with("00000010 "));
+    assert!(dump.ends_with("00000012\n"));
 }
 
 #[rstest]
@@ -229,3 +275,585 @@ fn test_load_data_invalid_svg(#[case] data: &[u8], #[case] err_msg: Option<&str>
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().to_string(), err_msg.unwrap());
 }
+
+#[rstest]
+#[case(vec![0], "1")]
+#[case(vec![0, 1, 2, 4], "1,2,3,5")]
+fn test_format_pages(#[case] indices: Vec<u16>, #[case] expected: &str) {
+    let pages = format_pages(&indices);
+    assert_eq!(pages, expected);
+    assert_eq!(parse_pages(&pages).unwrap(), Some(indices));
+}
+
+fn zip_data(entries: &[(&str, &str)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in entries {
+        writer
+            .start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut writer, content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[rstest]
+#[case(vec![("mimetype", "application/vnd.oasis.opendocument.text")], Some("odt"))]
+#[case(vec![("mimetype", "application/vnd.oasis.opendocument.spreadsheet")], Some("ods"))]
+#[case(vec![("mimetype", "application/epub+zip")], None)]
+#[case(vec![("[Content_Types].xml", "<Override ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>")], Some("docx"))]
+#[case(vec![("[Content_Types].xml", "<Override ContentType=\"application/vnd.openxmlformats-officedocument.presentationml.presentation.main+xml\"/>")], Some("pptx"))]
+#[case(vec![("test.txt", "test")], None)]
+fn test_detect_office_format_zip(
+    #[case] entries: Vec<(&str, &str)>,
+    #[case] expected: Option<&str>,
+) {
+    assert_eq!(detect_office_format(&zip_data(&entries)), expected);
+}
+
+/// Builds a compound file with 512-byte sectors: the FAT in sector 0, the directory with the
+/// root entry and up to three `streams` below it in sector 1, and `payload` in sector 2.
+fn ole2_data(streams: &[&str], payload: &[u8]) -> Vec<u8> {
+    const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+    const NO_ENTRY: u32 = 0xFFFF_FFFF;
+    let put = |data: &mut Vec<u8>, offset: usize, value: u32| {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+    };
+
+    let mut data = vec![0xFF; 512 * 4];
+    data[..0x4C].fill(0);
+    data[..8].copy_from_slice(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]);
+    data[0x1E] = 9;
+    put(&mut data, 0x2C, 1);
+    put(&mut data, 0x30, 1);
+    put(&mut data, 0x44, END_OF_CHAIN);
+    put(&mut data, 0x4C, 0);
+    // FAT: the FAT sector itself, then the directory and the payload ending their chains
+    put(&mut data, 512, 0xFFFF_FFFD);
+    put(&mut data, 516, END_OF_CHAIN);
+    put(&mut data, 520, END_OF_CHAIN);
+
+    let names = std::iter::once("Root Entry").chain(streams.iter().copied());
+    for (i, name) in names.enumerate() {
+        let entry = 1024 + i * 128;
+        data[entry..entry + 128].fill(0);
+        let name: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        data[entry..entry + name.len()].copy_from_slice(&name);
+        data[entry + 0x40] = name.len() as u8 + 2;
+        data[entry + 0x42] = if i == 0 { 5 } else { 2 };
+        // the root points to the first stream, each stream to the next one as right sibling
+        let next = if i < streams.len() { i as u32 + 1 } else { NO_ENTRY };
+        put(&mut data, entry + 0x44, NO_ENTRY);
+        put(&mut data, entry + 0x48, if i == 0 { NO_ENTRY } else { next });
+        put(&mut data, entry + 0x4C, if i == 0 { next } else { NO_ENTRY });
+    }
+    data[1536..1536 + payload.len()].copy_from_slice(payload);
+    data
+}
+
+#[rstest]
+#[case(vec!["WordDocument", "1Table"], Some("doc"))]
+#[case(vec!["Book"], Some("xls"))]
+#[case(vec!["Current User", "PowerPoint Document"], Some("ppt"))]
+#[case(vec!["workbook"], Some("xls"))]
+#[case(vec!["Contents"], None)]
+#[case(vec![], None)]
+fn test_detect_office_format_ole2(#[case] streams: Vec<&str>, #[case] expected: Option<&str>) {
+    // stream names in the payload are not directory entries
+    let payload: Vec<u8> = "Book\0WordDocument\0"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let data = ole2_data(&streams, &payload);
+    assert_eq!(detect_office_format(&data), expected);
+    // a truncated file has no directory
+    assert_eq!(detect_office_format(&data[..1024]), None);
+}
+
+#[rstest]
+#[case(b"{\\rtf1\\ansi test}", Some("rtf"))]
+#[case(PNG_DATA, None)]
+#[case(b"a,b,c\n1,2,3\n", None)]
+fn test_detect_office_format(#[case] data: &[u8], #[case] expected: Option<&str>) {
+    assert_eq!(detect_office_format(data), expected);
+}
+
+#[rstest]
+#[case(vec![("xl/workbook.xml", "<workbook><sheets><sheet name=\"Data\" sheetId=\"1\"/><sheet name=\"P&amp;L\" sheetId=\"2\"/></sheets></workbook>")], Some(vec!["Data", "P&L"]))]
+#[case(vec![("content.xml", "<office:spreadsheet><table:table table:name=\"Sheet1\"><table:table-row><table:table-cell office:value-type=\"float\" office:value=\"1\"/></table:table-row></table:table><table:table table:name=\"Summary\"><draw:frame/></table:table></office:spreadsheet>")], Some(vec!["Sheet1", "Summary"]))]
+// hidden and empty sheets are not exported
+#[case(vec![
+    ("xl/workbook.xml", "<workbook><sheets><sheet name=\"Data\" sheetId=\"1\" r:id=\"rId1\"/><sheet name=\"Empty\" sheetId=\"2\" r:id=\"rId2\"/><sheet name=\"Hidden\" sheetId=\"3\" state=\"hidden\" r:id=\"rId1\"/></sheets></workbook>"),
+    ("xl/_rels/workbook.xml.rels", "<Relationships><Relationship Id=\"rId1\" Target=\"worksheets/sheet1.xml\"/><Relationship Id=\"rId2\" Target=\"/xl/worksheets/sheet2.xml\"/></Relationships>"),
+    ("xl/worksheets/sheet1.xml", "<worksheet><sheetData><row r=\"1\"><c r=\"A1\"><v>1</v></c></row></sheetData></worksheet>"),
+    ("xl/worksheets/sheet2.xml", "<worksheet><sheetData/></worksheet>"),
+], Some(vec!["Data"]))]
+#[case(vec![("content.xml", "<office:automatic-styles><style:style style:name=\"ta2\" style:family=\"table\"><style:table-properties table:display=\"false\"/></style:style></office:automatic-styles><office:spreadsheet><table:table table:name=\"Data\" table:style-name=\"ta1\"><table:table-cell office:value-type=\"string\"/></table:table><table:table table:name=\"Hidden\" table:style-name=\"ta2\"><table:table-cell office:value-type=\"string\"/></table:table><table:table table:name=\"Empty\"><table:table-row/></table:table></office:spreadsheet>")], Some(vec!["Data"]))]
+#[case(vec![("word/document.xml", "<document/>")], None)]
+fn test_spreadsheet_sheet_names(
+    #[case] entries: Vec<(&str, &str)>,
+    #[case] expected: Option<Vec<&str>>,
+) {
+    let names = spreadsheet_sheet_names(&zip_data(&entries));
+    assert_eq!(
+        names,
+        expected.map(|names| names.into_iter().map(String::from).collect())
+    );
+}
+
+#[rstest]
+#[case("1", 0)]
+#[case("3", 2)]
+#[case("Summary", 1)]
+#[case("summary", 1)]
+// a sheet named like a number is selected by its name
+#[case("2024", 2)]
+#[case("2", 1)]
+fn test_resolve_sheet(#[case] sheet: &str, #[case] expected: u16) {
+    let names = vec!["Data".to_string(), "Summary".to_string(), "2024".to_string()];
+    assert_eq!(resolve_sheet(sheet, Some(&names)).unwrap(), expected);
+}
+
+#[rstest]
+#[case("0", Some(vec!["Data".to_string()]))]
+#[case("Missing", Some(vec!["Data".to_string()]))]
+#[case("Data", None)]
+fn test_resolve_sheet_invalid(#[case] sheet: &str, #[case] names: Option<Vec<String>>) {
+    assert!(resolve_sheet(sheet, names.as_deref()).is_err());
+}
+
+#[rstest]
+#[case("1024", 1024)]
+#[case("1K", 1024)]
+#[case("500M", 500 * 1024 * 1024)]
+#[case("2G", 2 * 1024 * 1024 * 1024)]
+#[case("1.5GiB", 1536 * 1024 * 1024)]
+#[case("10mb", 10 * 1024 * 1024)]
+fn test_parse_size(#[case] input: &str, #[case] expected: u64) {
+    assert_eq!(parse_size(input).unwrap(), expected);
+}
+
+#[rstest]
+#[case("")]
+#[case("G")]
+#[case("-1M")]
+#[case("1X")]
+fn test_parse_size_invalid(#[case] input: &str) {
+    assert!(parse_size(input).is_err());
+}
+
+#[rstest]
+#[case(512, "512B")]
+#[case(1536, "1.5K")]
+#[case(2 * 1024 * 1024 * 1024, "2.0G")]
+fn test_format_size(#[case] size: u64, #[case] expected: &str) {
+    assert_eq!(format_size(size), expected);
+}
+
+#[rstest]
+#[case("30", 30)]
+#[case("30s", 30)]
+#[case("15m", 15 * 60)]
+#[case("12h", 12 * 60 * 60)]
+#[case("7d", 7 * 24 * 60 * 60)]
+#[case("2w", 14 * 24 * 60 * 60)]
+fn test_parse_duration(#[case] input: &str, #[case] expected: u64) {
+    assert_eq!(
+        parse_duration(input).unwrap(),
+        std::time::Duration::from_secs(expected)
+    );
+}
+
+#[rstest]
+#[case("")]
+#[case("d")]
+#[case("7y")]
+#[case("-7d")]
+fn test_parse_duration_invalid(#[case] input: &str) {
+    assert!(parse_duration(input).is_err());
+}
+
+#[test]
+fn test_sort_plugins() {
+    let config: PluginConfig = toml::from_str(
+        r#"
+        [b]
+        extensions = ["svg"]
+        output = "image"
+        path = "b"
+
+        [a]
+        extensions = ["svg"]
+        output = "image"
+        path = "a"
+
+        [low]
+        extensions = ["svg"]
+        output = "image"
+        path = "low"
+        priority = -1
+
+        [high]
+        extensions = ["svg"]
+        output = "image"
+        path = "high"
+        priority = 10
+        mode = "fallback"
+        "#,
+    )
+    .unwrap();
+
+    let plugins = sort_plugins(config.plugins);
+    let names: Vec<_> = plugins.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["high", "a", "b", "low"]);
+    assert_eq!(plugins[0].1.mode, PluginMode::Fallback);
+    assert_eq!(plugins[1].1.mode, PluginMode::Override);
+}
+
+#[rstest]
+#[case(PNG_DATA, "image/png")]
+#[case(SVG_DATA, "image/svg+xml")]
+#[case(b"%PDF-1.7", "application/pdf")]
+#[case(b"\x00\x00\x00\x18ftypheic", "image/heic")]
+#[case(b"\x00\x00\x00\x18ftypisom", "video/mp4")]
+#[case(b"RIFF\x00\x00\x00\x00WAVEfmt ", "audio/wav")]
+#[case(b"<!DOCTYPE html><html></html>", "text/html")]
+#[case(b"hello world", "text/plain")]
+#[case(b"\x00\x01\x02\x03", "application/octet-stream")]
+fn test_sniff_mime(#[case] data: &[u8], #[case] expected: &str) {
+    assert_eq!(sniff_mime(data), expected);
+}
+
+#[test]
+fn test_sniff_mime_tar() {
+    let mut data = vec![0u8; 512];
+    data[257..262].copy_from_slice(b"ustar");
+    assert_eq!(sniff_mime(&data), "application/x-tar");
+}
+
+#[rstest]
+#[case("image/png", "image/png", true)]
+#[case("image/png", "IMAGE/*", true)]
+#[case("image/png", "*/*", true)]
+#[case("image/png", "video/*", false)]
+#[case("image/png", "image/jpeg", false)]
+fn test_mime_matches(#[case] mime: &str, #[case] pattern: &str, #[case] expected: bool) {
+    assert_eq!(mime_matches(mime, pattern), expected);
+}
+
+#[rstest]
+#[case(b"\x00\x00\x00\x10DATA....", "dat", None, true)] // magic at offset 4
+#[case(b"DATA....", "dat", None, false)]
+#[case(PNG_DATA, "", None, true)] // mime type
+#[case(b"plain", "", Some("/src/archive.tar.gz"), true)] // glob on file name
+#[case(b"plain", "", Some("/src/Dockerfile"), true)]
+#[case(b"plain", "", Some("/src/Dockerfile.bak"), false)]
+#[case(b"#!/usr/bin/env python3\n", "", None, true)] // header regex
+#[case(b"plain", "txt", None, false)]
+fn test_plugin_matches(
+    #[case] data: &[u8],
+    #[case] extension: &str,
+    #[case] path: Option<&str>,
+    #[case] expected: bool,
+) {
+    let plugin: Plugin = toml::from_str(
+        r#"
+        magic = [{ offset = 4, bytes = "44 41 54 41" }]
+        mime-types = ["image/*"]
+        globs = ["*.tar.gz", "Dockerfile"]
+        header-regex = "^#!.*python"
+        output = "text"
+        path = "inspect"
+        "#,
+    )
+    .unwrap();
+
+    let path = path.map(PathBuf::from);
+    let mime = sniff_mime(data);
+    assert_eq!(
+        plugin.matches(data, mime, extension, path.as_deref()),
+        expected
+    );
+}
+
+#[test]
+fn test_load_plugin_output_path_is_text() {
+    // the plugin prints its input, a path to an image
+    let plugin: Plugin = toml::from_str(
+        r#"
+        output = "auto"
+        path = "cat {}"
+        placeholder = "{}"
+        "#,
+    )
+    .unwrap();
+
+    let result = load_plugin(&default_ctx(), b"tests/fixtures/test.png\n", "", &plugin).unwrap();
+    assert!(matches!(result, LoadResult::Text(text) if text == b"tests/fixtures/test.png\n"));
+}
+
+#[rstest]
+#[case(r#"sh -c "echo '  broken  ' >&2; exit 2""#, "Plugin exited with error code Some(2): broken")]
+// nothing on stderr, the message printed to stdout is reported instead
+#[case(r#"sh -c "echo '  broken  '; exit 3""#, "Plugin exited with error code Some(3): broken")]
+fn test_load_plugin_failed(#[case] path: &str, #[case] err_msg: &str) {
+    let mut plugin: Plugin = toml::from_str(
+        r#"
+        output = "text"
+        path = ""
+        "#,
+    )
+    .unwrap();
+    plugin.path = path.to_string();
+
+    let result = load_plugin(&default_ctx(), PNG_DATA, "", &plugin);
+    assert_eq!(result.unwrap_err().to_string(), err_msg);
+}
+
+#[rstest]
+// writes one page image into the output directory
+#[case("cp {} {O}/1.png", Some(vec![4]), "Page index out of range (must be <= 1)")]
+#[case("true {} {O}", None, "Plugin returned no output")]
+fn test_load_plugin_output_dir_invalid(
+    #[case] path: &str,
+    #[case] page_indices: Option<Vec<u16>>,
+    #[case] err_msg: &str,
+) {
+    let mut plugin: Plugin = toml::from_str(
+        r#"
+        output = "image"
+        path = ""
+        placeholder = "{}"
+        output-placeholder = "{O}"
+        output-dir = true
+        "#,
+    )
+    .unwrap();
+    plugin.path = path.to_string();
+    let ctx = KvContext {
+        page_indices,
+        cache_mode: CacheMode::Disabled,
+        ..default_ctx()
+    };
+
+    let result = load_plugin(&ctx, PNG_DATA, "", &plugin);
+    assert_eq!(result.unwrap_err().to_string(), err_msg);
+}
+
+#[test]
+fn test_plugin_invalid_regex() {
+    let result = toml::from_str::<Plugin>(
+        r#"
+        header-regex = "(unclosed"
+        output = "text"
+        path = "inspect"
+        "#,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_read_plugin_config_unknown_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("plugins.toml");
+    std::fs::write(
+        &path,
+        "[eps]\nextensions = [\"eps\"]\noutput = \"image\"\npath = \"gs\"\nextention = \"ps\"\n",
+    )
+    .unwrap();
+
+    let message = read_plugin_config(&path).unwrap_err().to_string();
+    assert!(message.contains("line 5"), "{}", message);
+    assert!(message.contains("extention"), "{}", message);
+}
+
+#[rstest]
+#[case("path = \"gs {}\"\nplaceholder = \"{}\"", true)]
+#[case("path = \"\"", false)]
+#[case(
+    "path = \"gs {}\"\nplaceholder = \"{}\"\noutput-placeholder = \"{}\"",
+    false
+)]
+#[case("path = \"gs\"\nstdin-closed = true", false)]
+#[case("path = \"gs\"\noutput-dir = true", false)]
+fn test_plugin_validate(#[case] config: &str, #[case] valid: bool) {
+    let plugin: Plugin = toml::from_str(&format!("output = \"image\"\n{}", config)).unwrap();
+    assert_eq!(plugin.validate().is_ok(), valid);
+}
+
+#[test]
+fn test_find_executable() {
+    assert!(find_executable("sh").is_some());
+    assert!(find_executable("kv-missing-program").is_none());
+    assert!(find_executable("/kv/missing/program").is_none());
+}
+
+#[rstest]
+#[case(Handler::Plugin("eps".to_string()), "plugin eps")]
+#[case(Handler::Builtin { input_type: InputType::Svg, fallbacks: vec![] }, "built-in svg")]
+#[case(Handler::Builtin { input_type: InputType::Auto, fallbacks: vec!["a".to_string(), "b".to_string()] }, "built-in image (text or hex dump if it cannot be decoded), fallback plugins: a, b")]
+fn test_handler_display(#[case] handler: Handler, #[case] expected: &str) {
+    assert_eq!(handler.to_string(), expected);
+}
+
+#[test]
+fn test_find_project_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("a").join("b");
+    std::fs::create_dir_all(&nested).unwrap();
+    assert_eq!(find_project_config(&nested), None);
+
+    let config = dir.path().join(".kv").join("plugins.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "").unwrap();
+    assert_eq!(find_project_config(&nested), Some(config));
+}
+
+#[test]
+fn test_project_config_opt_in() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join(".kv").join("plugins.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "").unwrap();
+
+    // repositories must not run their commands just by viewing their files
+    assert_eq!(project_config(&PluginOptions::default(), dir.path()), None);
+    let options = PluginOptions {
+        project: true,
+        ..PluginOptions::default()
+    };
+    assert_eq!(project_config(&options, dir.path()), Some(config));
+}
+
+#[test]
+fn test_read_plugin_configs_override() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = dir.path().join("base.toml");
+    let project = dir.path().join("project.toml");
+    std::fs::write(
+        &base,
+        "[eps]\nextensions = [\"eps\"]\noutput = \"image\"\npath = \"gs\"\n\n[xml]\nextensions = [\"xml\"]\noutput = \"svg\"\npath = \"convert-xml\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &project,
+        "[eps]\nextensions = [\"eps\", \"ps\"]\noutput = \"pdf\"\npath = \"ps2pdf - -\"\n",
+    )
+    .unwrap();
+
+    let plugins = read_plugin_configs(&[base.clone(), project]).unwrap();
+    assert_eq!(plugins.len(), 2);
+    assert_eq!(plugins["eps"].path, "ps2pdf - -");
+    assert_eq!(plugins["xml"].path, "convert-xml");
+
+    // a missing explicit config is an error
+    assert!(read_plugin_configs(&[base, dir.path().join("missing.toml")]).is_err());
+}
+
+#[test]
+fn test_read_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    assert_eq!(read_settings(&path).unwrap(), Settings::default());
+
+    std::fs::write(
+        &path,
+        "background = true\ncolor = \"#1e1e2e\"\nmode = \"zlib\"\n",
+    )
+    .unwrap();
+    let settings = read_settings(&path).unwrap();
+    assert_eq!(settings.background, Some(true));
+    assert_eq!(settings.color.as_deref(), Some("#1e1e2e"));
+    assert_eq!(settings.mode.as_deref(), Some("zlib"));
+
+    std::fs::write(&path, "background = true\nmdoe = \"zlib\"\n").unwrap();
+    let err = read_settings(&path).unwrap_err().to_string();
+    assert!(err.contains("line 2"), "{}", err);
+}
+
+#[test]
+fn test_settings_from_env() {
+    let env = |name: &str| match name {
+        "KV_BACKGROUND" => Some("1".to_string()),
+        "KV_MODE" => Some("raw".to_string()),
+        "KV_COLOR" => Some(String::new()),
+        "KV_PROJECT_PLUGINS" => Some("off".to_string()),
+        _ => None,
+    };
+    let settings = Settings::from_env(env);
+    assert_eq!(settings.background, Some(true));
+    assert_eq!(settings.project_plugins, Some(false));
+    assert_eq!(settings.mode.as_deref(), Some("raw"));
+    assert_eq!(settings.color, None);
+
+    // an invalid value skips only its key
+    let settings = Settings::from_env(|name| match name {
+        "KV_BACKGROUND" => Some("maybe".to_string()),
+        "KV_MODE" => Some("raw".to_string()),
+        _ => None,
+    });
+    assert_eq!(settings.background, None);
+    assert_eq!(settings.mode.as_deref(), Some("raw"));
+
+    // the environment overrides the configuration file
+    let file = Settings {
+        mode: Some("zlib".to_string()),
+        color: Some("#1e1e2e".to_string()),
+        ..Settings::default()
+    };
+    let merged = file.merge(settings);
+    assert_eq!(merged.mode.as_deref(), Some("raw"));
+    assert_eq!(merged.color.as_deref(), Some("#1e1e2e"));
+}
+
+#[rstest]
+#[case("12", Some((12, 12)))]
+#[case("10:40", Some((10, 40)))]
+#[case(":40", Some((1, 40)))]
+#[case("10:", Some((10, usize::MAX)))]
+#[case("10:+5", Some((10, 15)))]
+#[case("0", None)]
+#[case("40:10", None)]
+#[case(":", None)]
+#[case("a:b", None)]
+fn test_parse_line_range(#[case] range: &str, #[case] expected: Option<(usize, usize)>) {
+    assert_eq!(parse_line_range(range).ok(), expected);
+}
+
+#[rstest]
+#[case(b"fn main() {}\n".repeat(100), InputType::Auto, true)]
+#[case("grüße\n".repeat(100).as_bytes()[..595].to_vec(), InputType::Auto, true)]
+#[case(PNG_DATA.to_vec(), InputType::Auto, false)]
+#[case(PNG_DATA.to_vec(), InputType::Text, true)]
+#[case(b"<svg></svg>".to_vec(), InputType::Auto, false)]
+#[case(b"\x00\xff\xfe".repeat(100), InputType::Auto, false)]
+fn test_is_streamable_text(
+    #[case] header: Vec<u8>,
+    #[case] input_type: InputType,
+    #[case] expected: bool,
+) {
+    let mut ctx = default_ctx();
+    ctx.input_type = input_type;
+    assert_eq!(is_streamable_text(&ctx, &header, ""), expected);
+}
+
+#[test]
+fn test_load_large_text_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.log");
+    std::fs::write(&path, "line\n".repeat(SNIFF_SIZE)).unwrap();
+
+    let ctx = default_ctx();
+    let result = load_file(&ctx, &path).unwrap();
+    assert!(matches!(result, LoadResult::TextFile(p) if p == path));
+
+    let data = read_file(File::open(&path).unwrap(), true).unwrap();
+    assert!(matches!(data, FileData::Mapped(_)));
+    assert_eq!(data.len(), 5 * SNIFF_SIZE);
+    // text is never decoded in place
+    assert!(!is_decoded_in_place(&ctx, &data[..SNIFF_SIZE], "log"));
+    let data = read_file(File::open(&path).unwrap(), false).unwrap();
+    assert!(matches!(data, FileData::Read(_)));
+    let data = read_input_file(&ctx, File::open(&path).unwrap(), "log").unwrap();
+    assert!(matches!(data, FileData::Read(_)));
+    assert_eq!(data.len(), 5 * SNIFF_SIZE);
+}
@@ -1,4 +1,6 @@
 // implementation tests
+#![allow(clippy::too_many_arguments)]
+
 use super::*;
 use rstest::rstest;
 use std::io::Cursor;
@@ -9,6 +11,7 @@ const SVG_DATA: &[u8] = include_bytes!("../tests/fixtures/test.svg");
 // dummy config
 fn default_conf() -> Config {
     Config {
+        command: None,
         files: vec![],
         width: None,
         height: None,
@@ -16,7 +19,11 @@ fn default_conf() -> Config {
         fullheight: false,
         resize: false,
         noresize: false,
+        no_orient: false,
+        rotate: RotateOption::None,
+        flip: None,
         background: false,
+        no_background: false,
         color: "#FFFFFF".to_string(),
         mode: ModeOption::Png,
         output: None,
@@ -24,12 +31,44 @@ fn default_conf() -> Config {
         input: InputTypeOption::Auto,
         pages: "1".to_string(),
         all: false,
+        markdown_theme: MarkdownThemeOption::Light,
         language: None,
         no_newline: false,
+        theme: None,
+        line_numbers: false,
+        grid: false,
+        header: false,
+        lines: vec![],
+        highlight_line: vec![],
+        tab_width: None,
+        wrap: false,
+        render_text: false,
+        font: None,
+        bytes: None,
+        list_themes: false,
+        list_languages: false,
+        info: false,
+        json: false,
+        sheet: None,
+        notes: false,
+        soffice: None,
+        office_timeout: 120,
         no_cache: false,
+        cache_max_size: 1 << 30,
+        cache_dir: None,
+        pdfium: None,
         printname: true, // default to true for tests
+        browser: None,
+        browser_ws: None,
+        print: false,
+        no_download: false,
+        offline: false,
+        no_js: false,
         tty: false,
         remove: false,
+        plugins: false,
+        plugin_config: None,
+        project_plugins: false,
     }
 }
 
@@ -53,6 +92,7 @@ fn run_test(
         conf,
         term_size,
         is_input_available,
+        false,
         cache_dir,
     )
     .unwrap();
@@ -143,6 +183,7 @@ fn test_output() {
         conf,
         (800, 400),
         false,
+        false,
         None,
     )
     .unwrap();
@@ -152,12 +193,37 @@ fn test_output() {
     assert!(output.starts_with(b"\x89PNG"));
 }
 
+#[rstest]
+#[case("out.png")]
+#[case("out.pdf")]
+fn test_output_multiple_files(#[case] output: &str) {
+    // exported PDFs would be concatenated into a single invalid file
+    let mut conf = default_conf();
+    conf.files = vec![
+        "tests/fixtures/test.pdf".into(),
+        "tests/fixtures/test.pdf".into(),
+    ];
+    conf.output = Some(output.to_string());
+    run_test(
+        conf,
+        false,
+        Cursor::new(&[]),
+        "",
+        "Error: Cannot specify multiple files with --output\n",
+        1,
+        false,
+        (800, 400),
+        None,
+    );
+}
+
 #[rstest]
 #[case(vec![],"0", false, "Error: Invalid page range\n")]
 #[case(vec![],"-1", false, "Error: Invalid page range\n")]
 #[case(vec!["tests/fixtures/test.pdf".into()],"2", false, "tests/fixtures/test.pdf\nError loading tests/fixtures/test.pdf: Page index out of range (must be <= 1)\n")]
-#[case(vec!["tests/fixtures/test.pdf".into(),"tests/fixtures/test.png".into()],"1", false, "Error: Cannot specify multiple files with --pages\n")]
+#[case(vec!["tests/fixtures/test.pdf".into(),"tests/fixtures/test.png".into()],"2", false, "Error: Cannot specify multiple files with non-default --pages option\n")]
 #[case(vec!["tests/fixtures/test.pdf".into()],"1", true, "tests/fixtures/test.pdf\n")]
+#[case(vec!["tests/fixtures/test.jpg".into(),"tests/fixtures/test.png".into()],"1", true, "tests/fixtures/test.jpg\ntests/fixtures/test.png\n")]
 fn test_pages(
     #[case] files: Vec<PathBuf>,
     #[case] pages: &str,
@@ -325,7 +391,7 @@ fn test_no_input() {
 #[rstest]
 #[case(vec!["tests/fixtures/test.png".into()], "tests/fixtures/test.png\n", 0)]
 #[case(vec!["tests/fixtures/test.jpg".into(), "tests/fixtures/test.png".into()], "tests/fixtures/test.jpg\ntests/fixtures/test.png\n", 0)]
-#[case(vec!["tests/fixtures/test.png".into(), "nonexistent".into()], "tests/fixtures/test.png\nnonexistent\nError loading nonexistent: Failed to open file\n", 1)]
+#[case(vec!["tests/fixtures/test.png".into(), "nonexistent".into()], "tests/fixtures/test.png\nnonexistent\nError loading nonexistent: Failed to open file: nonexistent\n", 1)]
 fn test_files(
     #[case] files: Vec<PathBuf>,
     #[case] expected_error: &str,
@@ -345,3 +411,369 @@ fn test_files(
         None,
     );
 }
+
+// cache
+#[rstest]
+#[case(CacheAction::Size, "0B  ", "")]
+#[case(CacheAction::Clear, "", "Removed 0 cached files\n")]
+#[case(CacheAction::Prune { older_than: None, max_size: Some(0) }, "", "Removed 0 cached files (0B)\n")]
+fn test_cache(
+    #[case] action: CacheAction,
+    #[case] expected_output: &str,
+    #[case] expected_error: &str,
+) {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let mut conf = default_conf();
+    conf.command = Some(Command::Cache { action });
+    run_test(
+        conf,
+        false,
+        Cursor::new(&[]),
+        expected_output,
+        expected_error,
+        0,
+        true,
+        (800, 400),
+        Some(cache_dir.path().to_path_buf()),
+    );
+}
+
+#[test]
+fn test_cache_list() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let name = format!("{}.pdf", "0".repeat(64));
+    std::fs::write(cache_dir.path().join(&name), b"%PDF").unwrap();
+
+    let mut conf = default_conf();
+    conf.command = Some(Command::Cache {
+        action: CacheAction::List,
+    });
+    run_test(
+        conf,
+        false,
+        Cursor::new(&[]),
+        &format!(
+            "      4B     0d  0h  {}\n",
+            cache_dir.path().join(&name).display()
+        ),
+        "",
+        0,
+        false,
+        (800, 400),
+        Some(cache_dir.path().to_path_buf()),
+    );
+}
+
+fn parse_args(args: &[&str]) -> (Config, ArgMatches) {
+    let matches = Config::command().get_matches_from(args);
+    let conf = Config::from_arg_matches(&matches).unwrap();
+    (conf, matches)
+}
+
+#[test]
+fn test_apply_settings() {
+    let settings = Settings {
+        background: Some(true),
+        color: Some("#1e1e2e".to_string()),
+        mode: Some("zlib".to_string()),
+        resize: Some("width".to_string()),
+        cache_dir: Some("/tmp/kv-cache".into()),
+        project_plugins: Some(true),
+        ..Settings::default()
+    };
+
+    let (mut conf, matches) = parse_args(&["kv"]);
+    apply_settings(&mut conf, &matches, settings.clone(), &mut Vec::new()).unwrap();
+    assert!(conf.background);
+    assert!(conf.project_plugins);
+    assert_eq!(conf.color, "#1e1e2e");
+    assert_eq!(conf.mode, ModeOption::Zlib);
+    assert!(conf.fullwidth);
+    assert_eq!(conf.cache_dir, Some("/tmp/kv-cache".into()));
+
+    // command line flags override the settings
+    let (mut conf, matches) = parse_args(&["kv", "-m", "png", "-b", "-c", "#000000", "-w", "100"]);
+    apply_settings(&mut conf, &matches, settings.clone(), &mut Vec::new()).unwrap();
+    assert!(conf.background);
+    assert_eq!(conf.color, "#000000");
+    assert_eq!(conf.mode, ModeOption::Png);
+    assert!(!conf.fullwidth);
+    assert_eq!(conf.width, Some(100));
+
+    let (mut conf, matches) = parse_args(&["kv", "--no-background"]);
+    apply_settings(&mut conf, &matches, settings, &mut Vec::new()).unwrap();
+    assert!(!conf.background);
+}
+
+#[test]
+fn test_sheet_conflicts_with_pages() {
+    assert!(Config::try_parse_from(["kv", "--sheet", "2", "-P", "1", "book.xlsx"]).is_err());
+    assert!(Config::try_parse_from(["kv", "--sheet", "2", "book.xlsx"]).is_ok());
+}
+
+#[test]
+fn test_color_requires_background() {
+    let (mut conf, matches) = parse_args(&["kv", "-c", "#000000"]);
+    assert!(apply_settings(&mut conf, &matches, Settings::default(), &mut Vec::new()).is_err());
+    let (mut conf, matches) = parse_args(&["kv", "-b", "-c", "#000000", "--no-background"]);
+    assert!(apply_settings(&mut conf, &matches, Settings::default(), &mut Vec::new()).is_err());
+
+    // the background may be enabled by the settings
+    let settings = Settings {
+        background: Some(true),
+        ..Settings::default()
+    };
+    let (mut conf, matches) = parse_args(&["kv", "-c", "#000000"]);
+    apply_settings(&mut conf, &matches, settings, &mut Vec::new()).unwrap();
+    assert!(conf.background);
+    assert_eq!(conf.color, "#000000");
+
+    let conf = Config::try_parse_from(["kv", "-b", "--no-background"]).unwrap();
+    assert!(!conf.background);
+}
+
+#[rstest]
+#[case(Settings { mode: Some("jpeg".to_string()), ..Settings::default() }, "Warning: Invalid mode in settings: ")]
+#[case(Settings { resize: Some("stretch".to_string()), ..Settings::default() }, "Warning: Invalid resize mode in settings: ")]
+#[case(Settings { color: Some("blue".to_string()), ..Settings::default() }, "Warning: Invalid color in settings: ")]
+#[case(Settings { markdown_theme: Some("sepia".to_string()), ..Settings::default() }, "Warning: Invalid markdown theme in settings: ")]
+#[case(Settings { theme: Some("no-such-theme".to_string()), ..Settings::default() }, "Warning: Invalid theme in settings: no-such-theme")]
+#[case(Settings { font: Some("No Such Font 42".to_string()), ..Settings::default() }, "Warning: Invalid font in settings: Font not found: No Such Font 42")]
+#[case(Settings { pdfium: Some("/nonexistent/libpdfium.so".into()), ..Settings::default() }, "Warning: Invalid pdfium in settings: Not found: /nonexistent/libpdfium.so")]
+#[case(Settings { browser: Some("/nonexistent/chromium".into()), ..Settings::default() }, "Warning: Invalid browser in settings: Not found: /nonexistent/chromium")]
+fn test_apply_invalid_settings(#[case] settings: Settings, #[case] expected_warning: &str) {
+    let settings = Settings {
+        background: Some(true),
+        ..settings
+    };
+    let (mut conf, matches) = parse_args(&["kv"]);
+    let (default_conf, _) = parse_args(&["kv"]);
+    let mut err_output = Vec::new();
+    apply_settings(&mut conf, &matches, settings, &mut err_output).unwrap();
+
+    let err_str = String::from_utf8(err_output).unwrap();
+    assert!(err_str.starts_with(expected_warning), "{}", err_str);
+    // the invalid key keeps its default, the other keys are applied
+    assert_eq!(conf.mode, default_conf.mode);
+    assert_eq!(conf.color, default_conf.color);
+    assert_eq!(conf.markdown_theme, default_conf.markdown_theme);
+    assert!(!conf.resize && !conf.fullwidth && !conf.fullheight && !conf.noresize);
+    assert_eq!(conf.theme, None);
+    assert_eq!(conf.font, None);
+    assert_eq!(conf.pdfium, None);
+    assert_eq!(conf.browser, None);
+    assert!(conf.background);
+}
+
+#[test]
+fn test_apply_valid_settings() {
+    let browser = tempfile::NamedTempFile::new().unwrap();
+    let pdfium = tempfile::tempdir().unwrap();
+    let theme = list_themes().remove(0);
+    let settings = Settings {
+        theme: Some(theme.clone()),
+        pdfium: Some(pdfium.path().to_path_buf()),
+        browser: Some(browser.path().to_path_buf()),
+        ..Settings::default()
+    };
+    let (mut conf, matches) = parse_args(&["kv"]);
+    let mut err_output = Vec::new();
+    apply_settings(&mut conf, &matches, settings, &mut err_output).unwrap();
+
+    assert!(err_output.is_empty());
+    assert_eq!(conf.theme, Some(theme));
+    assert_eq!(conf.pdfium.as_deref(), Some(pdfium.path()));
+    assert_eq!(conf.browser.as_deref(), Some(browser.path()));
+}
+
+#[test]
+fn test_text_lines() {
+    let mut conf = default_conf();
+    conf.input = InputTypeOption::Text;
+    conf.printname = false;
+    conf.lines = vec![(2, 3)];
+
+    let mut output = Vec::new();
+    let input = Cursor::new(b"one\ntwo\nthree\nfour\n".as_slice());
+    let code = run(
+        &mut output,
+        Vec::new(),
+        input,
+        conf,
+        (100, 50),
+        true,
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    let output_str = String::from_utf8(output).unwrap();
+    assert!(output_str.contains("two") && output_str.contains("three"));
+    assert!(!output_str.contains("one") && !output_str.contains("four"));
+}
+
+#[rstest]
+fn test_hex_dump_fallback(#[values(false, true)] is_output_terminal: bool) {
+    let mut conf = default_conf();
+    conf.printname = false;
+    let mut output = Vec::new();
+    let input = Cursor::new(b"\x00\x01\x02\xff\xfe".as_slice());
+    let code = run(
+        &mut output,
+        Vec::new(),
+        input,
+        conf,
+        (100, 50),
+        true,
+        is_output_terminal,
+        None,
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    let output_str = String::from_utf8(output).unwrap();
+    assert!(output_str.starts_with("00000000 "));
+    assert!(output_str.ends_with("00000005\n"));
+    // escape codes only on a terminal
+    assert_eq!(output_str.contains('\x1b'), is_output_terminal);
+}
+
+#[test]
+fn test_output_text() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let mut conf = default_conf();
+    conf.files = vec!["Cargo.toml".into()];
+    conf.output = Some(temp_file.path().to_str().unwrap().to_string());
+    conf.printname = false;
+
+    let mut output = Vec::new();
+    let code = run(
+        &mut output,
+        Vec::new(),
+        Cursor::new(&[]),
+        conf,
+        (800, 400),
+        false,
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    assert!(output.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn test_output_text_not_clipped() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let mut conf = default_conf();
+    conf.files = vec!["Cargo.toml".into()];
+    conf.output = Some(temp_file.path().to_str().unwrap().to_string());
+    conf.printname = false;
+
+    let mut output = Vec::new();
+    let code = run(
+        &mut output,
+        Vec::new(),
+        Cursor::new(&[]),
+        conf,
+        (100, 50),
+        false,
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    let img = image::load_from_memory(&output).unwrap();
+    assert!(img.width() > 100 && img.height() > 50);
+}
+
+#[test]
+fn test_stream_large_stdin() {
+    let mut conf = default_conf();
+    conf.printname = false;
+    let data = (0..20_000)
+        .map(|i| format!("line {}\n", i))
+        .collect::<String>();
+    assert!(data.len() > SNIFF_SIZE);
+
+    let mut output = Vec::new();
+    let input = Cursor::new(data.as_bytes());
+    let code = run(
+        &mut output,
+        Vec::new(),
+        input,
+        conf,
+        (100, 50),
+        true,
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(code, 0);
+    let output_str = String::from_utf8(output).unwrap();
+    assert!(output_str.contains("line 0") && output_str.contains("line 19999"));
+}
+
+#[test]
+fn test_browser_ws_unreachable() {
+    let mut conf = default_conf();
+    conf.files = vec!["tests/fixtures/test.html".into()];
+    conf.browser_ws = Some("ws://127.0.0.1:9/devtools/browser/kv".to_string());
+    conf.no_cache = true;
+    run_test(
+        conf,
+        false,
+        Cursor::new(&[]),
+        "",
+        "tests/fixtures/test.html\nError loading tests/fixtures/test.html: Failed to connect to \
+         browser at ws://127.0.0.1:9/devtools/browser/kv\n",
+        1,
+        false,
+        (100, 50),
+        None,
+    );
+}
+
+#[rstest]
+#[case(&["kv", "cache", "clear"], Some(Command::Cache { action: CacheAction::Clear }), &[])]
+#[case(&["kv", "--", "cache"], None, &["cache"])]
+#[case(&["kv", "image.png", "plugins"], None, &["image.png", "plugins"])]
+#[case(&["kv", "-i", "text", "cache"], None, &["cache"])]
+fn test_subcommand_or_files(
+    #[case] args: &[&str],
+    #[case] expected_command: Option<Command>,
+    #[case] expected_files: &[&str],
+) {
+    let conf = Config::try_parse_from(args).unwrap();
+    assert_eq!(conf.command, expected_command);
+    assert_eq!(
+        conf.files,
+        expected_files.iter().map(PathBuf::from).collect::<Vec<_>>()
+    );
+}
+
+#[rstest]
+#[case(
+    false,
+    "tests/fixtures/test.png\n  format:      PNG\n  dimensions:  1x1\n"
+)]
+#[case(
+    true,
+    "\"name\": \"tests/fixtures/test.png\",\n    \"format\": \"PNG\",\n"
+)]
+fn test_info(#[case] json: bool, #[case] expected_output: &str) {
+    let mut conf = default_conf();
+    conf.files = vec!["tests/fixtures/test.png".into()];
+    conf.info = true;
+    conf.json = json;
+    run_test(
+        conf,
+        false,
+        Cursor::new(&[]),
+        expected_output,
+        "",
+        0,
+        true,
+        (100, 50),
+        None,
+    );
+}